@@ -31,6 +31,10 @@ struct PlayMatch {
     #[clap(value_parser)]
     opponent_name: String,
 
+    /// Additional opponents, for free-for-all maps
+    #[clap(value_parser)]
+    extra_opponent_names: Vec<String>,
+
     #[clap(value_parser, long = "map")]
     map_name: Option<String>,
 
@@ -84,6 +88,7 @@ async fn main() {
     let created_match = create_match(
         channel.clone(),
         play_match.opponent_name,
+        play_match.extra_opponent_names,
         play_match.map_name,
     )
     .await
@@ -104,6 +109,7 @@ async fn main() {
 async fn create_match(
     channel: Channel,
     opponent_name: String,
+    extra_opponent_names: Vec<String>,
     map_name: Option<String>,
 ) -> Result<pb::CreateMatchResponse, Status> {
     let mut client = ClientApiServiceClient::new(channel);
@@ -111,6 +117,7 @@ async fn create_match(
         .create_match(Request::new(pb::CreateMatchRequest {
             opponent_name,
             map_name: map_name.unwrap_or_default(),
+            extra_opponent_names,
         }))
         .await;
     res.map(|response| response.into_inner())
@@ -0,0 +1,9 @@
+import sys
+import time
+
+# echoes every line, but takes its time with the first one
+for num, line in enumerate(sys.stdin):
+    if num == 0:
+        time.sleep(0.3)
+    sys.stdout.write(line)
+    sys.stdout.flush()
@@ -0,0 +1,14 @@
+import sys
+import time
+
+# speaks the legacy protocol, sending two orders every turn,
+# but takes its time with the first turn
+turn_num = 1
+for line in sys.stdin:
+    if line.strip() != "go":
+        continue
+    if turn_num == 1:
+        time.sleep(0.3)
+    sys.stdout.write("0 1 {}\n0 1 {}\ngo\n".format(turn_num, turn_num))
+    sys.stdout.flush()
+    turn_num += 1
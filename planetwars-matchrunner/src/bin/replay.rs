@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process;
+
+use clap::Parser;
+use planetwars_matchrunner::match_log::read_log;
+use planetwars_matchrunner::replay::replay_match;
+
+/// Re-simulate a match log and check it against the current rules.
+#[derive(clap::Parser)]
+struct Args {
+    #[clap(value_parser)]
+    log_file: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let file = File::open(&args.log_file).expect("could not open log file");
+    let log = read_log(BufReader::new(file)).expect("could not read log file");
+    let report = replay_match(log).expect("could not replay match");
+
+    match report.divergence {
+        None => println!("replayed {} turns, no divergence", report.num_turns),
+        Some(divergence) => {
+            println!("replay diverged at turn {}", divergence.turn_num);
+            println!(
+                "logged:   {}",
+                serde_json::to_string(&divergence.expected).unwrap()
+            );
+            println!(
+                "replayed: {}",
+                serde_json::to_string(&divergence.actual).unwrap()
+            );
+            process::exit(1);
+        }
+    }
+}
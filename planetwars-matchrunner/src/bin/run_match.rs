@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use clap::Parser;
+use planetwars_matchrunner::bot_runner::Bot;
+use planetwars_matchrunner::docker_runner::DockerBotSpec;
+use planetwars_matchrunner::{
+    run_match, BotSpec, MatchConfig, MatchOutcome, MatchPlayer, TimeBank, TimeControl,
+    DEFAULT_MAX_TURNS, DEFAULT_TURN_TIMEOUT,
+};
+use planetwars_rules::protocol::ProtocolVersion;
+use planetwars_rules::{CombatRuleConfig, FogOfWar, MapSource, WinReason};
+
+const DOCKER_PREFIX: &str = "docker:";
+
+/// Play a match between bots on this machine, without a server.
+#[derive(clap::Parser)]
+struct Args {
+    /// Map file to play on
+    #[clap(value_parser)]
+    map: PathBuf,
+
+    /// The bots, in player order. A bot is either a command to run in the
+    /// current directory, such as "python3 bot.py", or a docker image
+    /// prefixed with "docker:".
+    #[clap(value_parser, required = true, min_values = 2)]
+    bots: Vec<String>,
+
+    #[clap(long, value_parser, default_value_t = DEFAULT_MAX_TURNS)]
+    max_turns: u64,
+
+    /// How long bots get to respond to a turn, in milliseconds
+    #[clap(long, value_parser, default_value_t = DEFAULT_TURN_TIMEOUT.as_millis() as u64)]
+    timeout_ms: u64,
+
+    /// How long bots get to respond to their first message, in milliseconds.
+    /// Defaults to the turn timeout.
+    #[clap(long, value_parser)]
+    first_turn_timeout_ms: Option<u64>,
+
+    /// Time bots can spend over the turn timeout during the whole match, in milliseconds
+    #[clap(long, value_parser)]
+    time_bank_ms: Option<u64>,
+
+    /// Time added to the time bank every turn, in milliseconds
+    #[clap(long, value_parser, default_value_t = 0, requires = "time-bank-ms")]
+    time_bank_increment_ms: u64,
+
+    /// Combat rule as JSON, such as '{"type": "defender_bonus", "bonus_percent": 20}'.
+    /// Defaults to the largest fleet winning.
+    #[clap(long, value_parser = parse_combat_rule)]
+    combat_rule: Option<CombatRuleConfig>,
+
+    /// Play with fog of war, where bots only see what is within this distance
+    /// of their planets
+    #[clap(long, value_parser)]
+    vision_radius: Option<f64>,
+
+    /// Where to write the match log
+    #[clap(long, value_parser, default_value = "match.log")]
+    log: PathBuf,
+}
+
+fn parse_combat_rule(combat_rule: &str) -> Result<CombatRuleConfig, serde_json::Error> {
+    serde_json::from_str(combat_rule)
+}
+
+fn bot_spec(bot: &str) -> Box<dyn BotSpec> {
+    match bot.strip_prefix(DOCKER_PREFIX) {
+        Some(image) => Box::new(DockerBotSpec {
+            image: image.to_string(),
+            binds: None,
+            argv: None,
+            working_dir: None,
+            pull: false,
+            credentials: None,
+        }),
+        None => Box::new(Bot {
+            working_dir: PathBuf::from("."),
+            argv: bot.split_whitespace().map(String::from).collect(),
+        }),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    let map_name = args
+        .map
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let config = MatchConfig {
+        map_name,
+        map: MapSource::File(args.map),
+        log_path: args.log.clone(),
+        players: args
+            .bots
+            .iter()
+            .map(|bot| MatchPlayer {
+                bot_spec: bot_spec(bot),
+                name: Some(bot.clone()),
+                protocol: ProtocolVersion::V1,
+                report_command_errors: false,
+            })
+            .collect(),
+        max_turns: args.max_turns,
+        time_control: TimeControl {
+            turn_timeout: Duration::from_millis(args.timeout_ms),
+            first_turn_timeout: args.first_turn_timeout_ms.map(Duration::from_millis),
+            time_bank: args.time_bank_ms.map(|time_bank_ms| TimeBank {
+                initial: Duration::from_millis(time_bank_ms),
+                increment: Duration::from_millis(args.time_bank_increment_ms),
+            }),
+        },
+        tiebreaks: Vec::new(),
+        rules_variant: None,
+        combat_rule: args.combat_rule.unwrap_or_default(),
+        fog_of_war: args
+            .vision_radius
+            .map(|vision_radius| FogOfWar { vision_radius }),
+        teams: Vec::new(),
+    };
+
+    match run_match(config).await {
+        Ok(outcome) => {
+            print_outcome(&outcome, &args.bots);
+            println!("log written to {}", args.log.display());
+        }
+        Err(err) => {
+            eprintln!("could not play match: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_outcome(outcome: &MatchOutcome, bots: &[String]) {
+    println!("match ended after {} turns", outcome.num_turns);
+    let reason = match outcome.win_reason {
+        Some(WinReason::Elimination) => "by elimination".to_string(),
+        Some(WinReason::Tiebreak(tiebreak)) => format!("on tiebreak {:?}", tiebreak),
+        None => String::new(),
+    };
+    match (outcome.winner, outcome.winning_team) {
+        (Some(winner), _) => println!("player {} won {}", winner, reason),
+        (None, Some(team)) => println!("team {} won {}", team, reason),
+        (None, None) => println!("the match ended in a draw"),
+    }
+    for (player_num, (bot, player_outcome)) in bots.iter().zip(&outcome.player_outcomes).enumerate()
+    {
+        let mut notes = Vec::new();
+        if let Some(reason) = &player_outcome.forfeit {
+            notes.push(format!("forfeited: {}", reason));
+        }
+        if player_outcome.crashed {
+            notes.push("crashed".to_string());
+        }
+        if player_outcome.had_errors {
+            notes.push("sent invalid commands".to_string());
+        }
+        if notes.is_empty() {
+            println!("player {}: {}", player_num + 1, bot);
+        } else {
+            println!("player {}: {} ({})", player_num + 1, bot, notes.join(", "));
+        }
+    }
+}
@@ -3,6 +3,9 @@ use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::Arc;
 use std::sync::Mutex;
+
+use async_trait::async_trait;
+use futures::FutureExt;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
 use tokio::process;
 use tokio::sync::mpsc;
@@ -13,6 +16,28 @@ use super::match_context::EventBus;
 use super::match_context::PlayerHandle;
 use super::match_context::RequestError;
 use super::match_context::RequestMessage;
+use super::match_context::UnansweredRequests;
+use super::match_log::{MatchLogMessage, MatchLogger, StdErrMessage};
+use super::{BotSpec, BotStartError};
+
+#[async_trait]
+impl BotSpec for Bot {
+    async fn run_bot(
+        &self,
+        player_id: u32,
+        event_bus: Arc<Mutex<EventBus>>,
+        match_logger: MatchLogger,
+    ) -> Result<Box<dyn PlayerHandle>, BotStartError> {
+        let process = self
+            .spawn(Stdio::piped())
+            .map_err(|err| BotStartError::SpawnFailed {
+                error: err.to_string(),
+            })?;
+        let handle = run_local_bot(player_id, event_bus, process, match_logger);
+        Ok(Box::new(handle))
+    }
+}
+
 // TODO: this is exactly the same as the docker bot handle.
 // should this abstraction be removed?
 pub struct LocalBotHandle {
@@ -32,14 +57,25 @@ impl PlayerHandle for LocalBotHandle {
     }
 }
 
-pub fn run_local_bot(player_id: u32, event_bus: Arc<Mutex<EventBus>>, bot: Bot) -> LocalBotHandle {
+/// Run a bot process, writing its stderr to the match log when it is piped
+pub fn run_local_bot(
+    player_id: u32,
+    event_bus: Arc<Mutex<EventBus>>,
+    mut process: BotProcess,
+    match_logger: MatchLogger,
+) -> LocalBotHandle {
     let (tx, rx) = mpsc::unbounded_channel();
 
+    if let Some(stderr) = process.child.stderr.take() {
+        tokio::spawn(log_stderr(stderr, player_id, match_logger.clone()));
+    }
     let runner = LocalBotRunner {
         event_bus,
         rx,
+        match_logger,
         player_id,
-        bot,
+        process: Some(process),
+        unanswered_requests: UnansweredRequests::default(),
     };
     let join_handle = tokio::spawn(runner.run());
 
@@ -49,24 +85,29 @@ pub fn run_local_bot(player_id: u32, event_bus: Arc<Mutex<EventBus>>, bot: Bot)
 pub struct LocalBotRunner {
     event_bus: Arc<Mutex<EventBus>>,
     rx: mpsc::UnboundedReceiver<RequestMessage>,
+    match_logger: MatchLogger,
     player_id: u32,
-    bot: Bot,
+    /// The bot process, until it terminates
+    process: Option<BotProcess>,
+    unanswered_requests: UnansweredRequests,
 }
 
 impl LocalBotRunner {
     pub async fn run(mut self) {
-        let mut process = self.bot.spawn_process();
-
         while let Some(request) = self.rx.recv().await {
-            let resp_fut = process.communicate(&request.content);
-            let result = timeout(request.timeout, resp_fut)
-                .await
-                // TODO: how can this failure be handled cleanly?
-                .expect("process read failed");
-            let result = match result {
-                Ok(line) => Ok(line.into_bytes()),
-                Err(_elapsed) => Err(RequestError::Timeout),
+            let result = match timeout(request.timeout, self.communicate(&request)).await {
+                Ok(Ok(line)) => Ok(line.into_bytes()),
+                // the process exited or closed its stdout
+                Ok(Err(_read_error)) => Err(RequestError::BotTerminated),
+                Err(_elapsed) => {
+                    self.unanswered_requests.record_timeout(&request);
+                    Err(RequestError::Timeout)
+                }
             };
+            if result == Err(RequestError::BotTerminated) {
+                // dropping the process kills it
+                self.process = None;
+            }
             let request_id = (self.player_id, request.request_id);
 
             self.event_bus
@@ -75,6 +116,62 @@ impl LocalBotRunner {
                 .resolve_request(request_id, result);
         }
     }
+
+    async fn communicate(&mut self, request: &RequestMessage) -> io::Result<String> {
+        let process = self
+            .process
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "bot has terminated"))?;
+        if let Some(content) = &request.content {
+            // the bot wrote these lines before it received this request,
+            // so they respond to earlier requests that timed out
+            for line in process.available_lines() {
+                self.unanswered_requests.record_late_response();
+                log_late_response(&self.match_logger, self.player_id, line);
+            }
+            process.write_line(content).await?;
+        }
+        loop {
+            let line = process.read_line().await?;
+            if !self
+                .unanswered_requests
+                .is_late_response(request, line.as_bytes())
+            {
+                return Ok(line);
+            }
+            log_late_response(&self.match_logger, self.player_id, line);
+        }
+    }
+}
+
+fn log_late_response(match_logger: &MatchLogger, player_id: u32, response: String) {
+    // the match might already be over
+    let _ = match_logger.send(MatchLogMessage::LateResponse {
+        player_id,
+        response,
+    });
+}
+
+/// Write every line the bot writes to stderr to the match log
+async fn log_stderr(stderr: process::ChildStderr, player_id: u32, match_logger: MatchLogger) {
+    let mut reader = BufReader::new(stderr);
+    let mut line = Vec::new();
+    while let Ok(num_bytes) = reader.read_until(b'\n', &mut line).await {
+        if num_bytes == 0 {
+            break;
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        let message = StdErrMessage {
+            player_id,
+            message: String::from_utf8_lossy(&line).to_string(),
+        };
+        if match_logger.send(MatchLogMessage::StdErr(message)).is_err() {
+            break;
+        }
+        line.clear();
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -84,41 +181,66 @@ pub struct Bot {
 }
 
 impl Bot {
+    /// Start the bot, letting it write to our stderr
     pub fn spawn_process(&self) -> BotProcess {
-        let mut child = process::Command::new(&self.argv[0])
+        self.spawn(Stdio::inherit()).expect("spawning failed")
+    }
+
+    /// Start the bot, with its stderr going to the given destination.
+    /// When it is piped, it can be taken from the child process.
+    pub fn spawn(&self, stderr: Stdio) -> io::Result<BotProcess> {
+        let program = self
+            .argv
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = process::Command::new(program)
             .args(&self.argv[1..])
             .current_dir(self.working_dir.clone())
             .kill_on_drop(true)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()
-            .expect("spawning failed");
+            .stderr(stderr)
+            .spawn()?;
 
         let stdout = child.stdout.take().unwrap();
         let reader = BufReader::new(stdout).lines();
 
-        BotProcess {
+        Ok(BotProcess {
             stdin: child.stdin.take().unwrap(),
             stdout: reader,
             child,
-        }
+        })
     }
 }
 
 pub struct BotProcess {
-    #[allow(dead_code)]
     pub child: process::Child,
     pub stdin: process::ChildStdin,
     pub stdout: Lines<BufReader<process::ChildStdout>>,
 }
 
 impl BotProcess {
-    // TODO: gracefully handle errors
     pub async fn communicate(&mut self, input: &[u8]) -> io::Result<String> {
+        self.write_line(input).await?;
+        self.read_line().await
+    }
+
+    pub async fn write_line(&mut self, input: &[u8]) -> io::Result<()> {
         self.stdin.write_all(input).await?;
-        self.stdin.write_u8(b'\n').await?;
+        self.stdin.write_u8(b'\n').await
+    }
+
+    pub async fn read_line(&mut self) -> io::Result<String> {
         let line = self.stdout.next_line().await?;
         line.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no response received"))
     }
+
+    /// Take the lines the bot already wrote, without waiting for more
+    pub fn available_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some(Ok(Some(line))) = self.stdout.next_line().now_or_never() {
+            lines.push(line);
+        }
+        lines
+    }
 }
@@ -6,15 +6,17 @@ use async_trait::async_trait;
 use bollard::container::{self, AttachContainerOptions, AttachContainerResults, LogOutput};
 use bollard::Docker;
 use bytes::{Bytes, BytesMut};
-use futures::{Stream, StreamExt};
+use futures::{FutureExt, Stream, StreamExt};
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio::time::timeout;
 
-use crate::match_context::{EventBus, PlayerHandle, RequestError, RequestMessage};
+use crate::match_context::{
+    EventBus, PlayerHandle, RequestError, RequestMessage, UnansweredRequests,
+};
 use crate::match_log::{MatchLogMessage, MatchLogger, StdErrMessage};
-use crate::BotSpec;
+use crate::{BotSpec, BotStartError};
 
 // TODO: this API needs a better design with respect to pulling
 // and general container management
@@ -41,17 +43,21 @@ impl BotSpec for DockerBotSpec {
         player_id: u32,
         event_bus: Arc<Mutex<EventBus>>,
         match_logger: MatchLogger,
-    ) -> Box<dyn PlayerHandle> {
-        let process = spawn_docker_process(self).await.unwrap();
+    ) -> Result<Box<dyn PlayerHandle>, BotStartError> {
+        let process = spawn_docker_process(self).await?;
         let handle = run_docker_bot(process, player_id, event_bus, match_logger);
-        return Box::new(handle);
+        Ok(Box::new(handle))
     }
 }
 
-async fn spawn_docker_process(
-    params: &DockerBotSpec,
-) -> Result<ContainerProcess, bollard::errors::Error> {
-    let docker = Docker::connect_with_socket_defaults()?;
+fn container_create_failed(err: bollard::errors::Error) -> BotStartError {
+    BotStartError::ContainerCreateFailed {
+        error: err.to_string(),
+    }
+}
+
+async fn spawn_docker_process(params: &DockerBotSpec) -> Result<ContainerProcess, BotStartError> {
+    let docker = Docker::connect_with_socket_defaults().map_err(container_create_failed)?;
 
     if params.pull {
         let mut create_image_stream = docker.create_image(
@@ -71,9 +77,10 @@ async fn spawn_docker_process(
         );
 
         while let Some(item) = create_image_stream.next().await {
-            // just consume the stream for now,
-            // and make noise when something breaks
-            let _info = item.expect("hit error in docker pull");
+            // just consume the stream for now
+            let _info = item.map_err(|err| BotStartError::ImagePullFailed {
+                error: err.to_string(),
+            })?;
         }
     }
 
@@ -103,16 +110,34 @@ async fn spawn_docker_process(
 
     let response = docker
         .create_container::<&str, String>(None, config)
-        .await?;
+        .await
+        .map_err(container_create_failed)?;
     let container_id = response.id;
 
-    docker
-        .start_container::<String>(&container_id, None)
-        .await?;
+    match start_and_attach(&docker, &container_id).await {
+        Ok(AttachContainerResults { output, input }) => Ok(ContainerProcess {
+            docker,
+            container_id,
+            stdin: input,
+            output,
+        }),
+        Err(err) => {
+            // don't leave the container behind
+            let _ = remove_container(&docker, &container_id).await;
+            Err(container_create_failed(err))
+        }
+    }
+}
+
+async fn start_and_attach(
+    docker: &Docker,
+    container_id: &str,
+) -> Result<AttachContainerResults, bollard::errors::Error> {
+    docker.start_container::<String>(container_id, None).await?;
 
-    let AttachContainerResults { output, input } = docker
+    docker
         .attach_container(
-            &container_id,
+            container_id,
             Some(AttachContainerOptions::<String> {
                 stdout: Some(true),
                 stderr: Some(true),
@@ -123,14 +148,22 @@ async fn spawn_docker_process(
                 ..Default::default()
             }),
         )
-        .await?;
-
-    Ok(ContainerProcess {
-        docker,
-        container_id,
-        stdin: input,
-        output,
-    })
+        .await
+}
+
+async fn remove_container(
+    docker: &Docker,
+    container_id: &str,
+) -> Result<(), bollard::errors::Error> {
+    docker
+        .remove_container(
+            container_id,
+            Some(bollard::container::RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await
 }
 
 struct ContainerProcess {
@@ -143,15 +176,7 @@ struct ContainerProcess {
 impl ContainerProcess {
     // &mut is required here to make terminate().await Sync
     async fn terminate(&mut self) -> Result<(), bollard::errors::Error> {
-        self.docker
-            .remove_container(
-                &self.container_id,
-                Some(bollard::container::RemoveContainerOptions {
-                    force: true,
-                    ..Default::default()
-                }),
-            )
-            .await
+        remove_container(&self.docker, &self.container_id).await
     }
 }
 
@@ -170,6 +195,7 @@ fn run_docker_bot(
         rx,
 
         stdout_buf: BytesMut::new(),
+        unanswered_requests: UnansweredRequests::default(),
     };
 
     let join_handle = tokio::spawn(bot_runner.run());
@@ -203,19 +229,23 @@ pub struct DockerBotRunner {
 
     stdout_buf: BytesMut,
     // stderr_buf: BytesMut,
+    unanswered_requests: UnansweredRequests,
 }
 
 impl DockerBotRunner {
     pub async fn run(mut self) {
         while let Some(request) = self.rx.recv().await {
-            let resp_fut = self.communicate(&request.content);
+            let resp_fut = self.communicate(&request);
             let result = timeout(request.timeout, resp_fut).await;
             let request_response = match result {
                 Ok(Ok(response)) => Ok(response.to_vec()),
                 // Read failed.
                 // TODO: better logging for errors
                 Ok(Err(_read_error)) => Err(RequestError::BotTerminated),
-                Err(_elapsed) => Err(RequestError::Timeout),
+                Err(_elapsed) => {
+                    self.unanswered_requests.record_timeout(&request);
+                    Err(RequestError::Timeout)
+                }
             };
             let request_id = (self.player_id, request.request_id);
 
@@ -231,9 +261,24 @@ impl DockerBotRunner {
             .expect("could not terminate process");
     }
 
-    pub async fn communicate(&mut self, input: &[u8]) -> io::Result<Bytes> {
-        self.write_line(input).await?;
-        self.read_line().await
+    pub async fn communicate(&mut self, request: &RequestMessage) -> io::Result<Bytes> {
+        if let Some(content) = &request.content {
+            // the bot wrote these lines before it received this request,
+            // so they respond to earlier requests that timed out
+            self.read_available_output();
+            while let Some(line) = self.take_line() {
+                self.unanswered_requests.record_late_response();
+                self.log_late_response(&line);
+            }
+            self.write_line(content).await?;
+        }
+        loop {
+            let line = self.read_line().await?;
+            if !self.unanswered_requests.is_late_response(request, &line) {
+                return Ok(line);
+            }
+            self.log_late_response(&line);
+        }
     }
 
     async fn write_line(&mut self, bytes: &[u8]) -> io::Result<()> {
@@ -244,32 +289,13 @@ impl DockerBotRunner {
     }
 
     async fn read_line(&mut self) -> io::Result<Bytes> {
-        while let Some(item) = self.process.output.next().await {
-            let log_output = item.expect("failed to get log output");
-            match log_output {
-                LogOutput::StdOut { message } => {
-                    self.stdout_buf.extend_from_slice(&message);
-                    if let Some(split_idx) = memchr::memchr(b'\n', &self.stdout_buf) {
-                        let line = self.stdout_buf.split_to(split_idx+1);
-                        return Ok(line.freeze())
-                    }
-                }
-                LogOutput::StdErr { mut message } => {
-                    // TODO
-                    if message.ends_with(b"\n") {
-                        message.truncate(message.len() - 1);
-                    }
-                    for line in message.split(|c| *c == b'\n') {
-                        let message = StdErrMessage {
-                            player_id: self.player_id,
-                            message: String::from_utf8_lossy(line).to_string(),
-                        };
-                        self.match_logger
-                            .send(MatchLogMessage::StdErr(message))
-                            .unwrap();
-                    }
-                }
-                _ => (),
+        loop {
+            if let Some(line) = self.take_line() {
+                return Ok(line);
+            }
+            match self.process.output.next().await {
+                Some(item) => self.handle_output(item.expect("failed to get log output")),
+                None => break,
             }
         }
 
@@ -281,4 +307,50 @@ impl DockerBotRunner {
             "no response received",
         ))
     }
+
+    /// Process the output the container already produced, without waiting for more
+    fn read_available_output(&mut self) {
+        while let Some(Some(item)) = self.process.output.next().now_or_never() {
+            self.handle_output(item.expect("failed to get log output"));
+        }
+    }
+
+    /// Take the next complete line from the stdout buffer
+    fn take_line(&mut self) -> Option<Bytes> {
+        let split_idx = memchr::memchr(b'\n', &self.stdout_buf)?;
+        Some(self.stdout_buf.split_to(split_idx + 1).freeze())
+    }
+
+    fn handle_output(&mut self, log_output: LogOutput) {
+        match log_output {
+            LogOutput::StdOut { message } => {
+                self.stdout_buf.extend_from_slice(&message);
+            }
+            LogOutput::StdErr { mut message } => {
+                // TODO
+                if message.ends_with(b"\n") {
+                    message.truncate(message.len() - 1);
+                }
+                for line in message.split(|c| *c == b'\n') {
+                    let message = StdErrMessage {
+                        player_id: self.player_id,
+                        message: String::from_utf8_lossy(line).to_string(),
+                    };
+                    self.match_logger
+                        .send(MatchLogMessage::StdErr(message))
+                        .unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn log_late_response(&self, line: &[u8]) {
+        let response = String::from_utf8_lossy(line).trim_end().to_string();
+        // the match might already be over
+        let _ = self.match_logger.send(MatchLogMessage::LateResponse {
+            player_id: self.player_id,
+            response,
+        });
+    }
 }
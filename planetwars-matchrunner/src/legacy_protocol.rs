@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use planetwars_rules::classic::{format_classic_state, parse_classic_order};
+use planetwars_rules::protocol as proto;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::match_context::{
+    EventBus, MatchCtx, PlayerHandle, Request, RequestMessage, RequestResult,
+};
+use crate::match_log::{MatchLogMessage, MatchLogger};
+use crate::{BotSpec, BotStartError};
+
+/// Runs a bot that speaks the line-based protocol of the original
+/// Planet Wars AI Challenge, translating to and from our JSON protocol.
+///
+/// Every turn, the bot receives the state as `P` and `F` lines followed by `go`,
+/// and responds with `origin destination ships` orders followed by `go`.
+/// Messages of newer protocol versions that do not carry a state are
+/// acknowledged without involving the bot.
+pub struct LegacyProtocolBotSpec {
+    pub bot_spec: Box<dyn BotSpec>,
+}
+
+#[async_trait]
+impl BotSpec for LegacyProtocolBotSpec {
+    async fn run_bot(
+        &self,
+        player_id: u32,
+        event_bus: Arc<Mutex<EventBus>>,
+        match_logger: MatchLogger,
+    ) -> Result<Box<dyn PlayerHandle>, BotStartError> {
+        // the wrapped bot answers to an event bus of its own, so that
+        // its responses can be translated before they are passed on
+        let bot_event_bus = Arc::new(Mutex::new(EventBus::new()));
+        let bot_handle = self
+            .bot_spec
+            .run_bot(player_id, bot_event_bus.clone(), match_logger.clone())
+            .await?;
+        let mut players = HashMap::new();
+        players.insert(player_id, bot_handle);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let adapter = LegacyProtocolAdapter {
+            player_id,
+            event_bus,
+            rx,
+            bot_ctx: MatchCtx::new(bot_event_bus, players, match_logger),
+            unfinished_turn: false,
+        };
+        let join_handle = tokio::spawn(adapter.run());
+        Ok(Box::new(LegacyProtocolBotHandle { tx, join_handle }))
+    }
+}
+
+pub struct LegacyProtocolBotHandle {
+    tx: mpsc::UnboundedSender<RequestMessage>,
+    join_handle: JoinHandle<()>,
+}
+
+impl PlayerHandle for LegacyProtocolBotHandle {
+    fn send_request(&mut self, r: RequestMessage) {
+        self.tx
+            .send(r)
+            .expect("failed to send message to legacy protocol adapter");
+    }
+
+    fn into_join_handle(self: Box<Self>) -> JoinHandle<()> {
+        self.join_handle
+    }
+}
+
+struct LegacyProtocolAdapter {
+    player_id: u32,
+    event_bus: Arc<Mutex<EventBus>>,
+    rx: mpsc::UnboundedReceiver<RequestMessage>,
+    bot_ctx: MatchCtx,
+    /// Whether the bot did not write the `go` of its last turn yet
+    unfinished_turn: bool,
+}
+
+impl LegacyProtocolAdapter {
+    async fn run(mut self) {
+        while let Some(request) = self.rx.recv().await {
+            // turn messages of all protocol versions contain the state fields
+            let state = request
+                .content
+                .as_deref()
+                .and_then(|content| serde_json::from_slice::<proto::State>(content).ok());
+            let result = match state {
+                Some(state) => self.play_turn(&state, request.timeout).await,
+                None => Ok(Vec::new()),
+            };
+            let request_id = (self.player_id, request.request_id);
+
+            self.event_bus
+                .lock()
+                .unwrap()
+                .resolve_request(request_id, result);
+        }
+
+        self.bot_ctx.shutdown().await;
+    }
+
+    async fn play_turn(
+        &mut self,
+        state: &proto::State,
+        timeout: Duration,
+    ) -> RequestResult<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        if self.unfinished_turn {
+            // the bot only reads the next state once it has finished its last turn,
+            // so the orders it is still writing are too late to play
+            let request = self.bot_ctx.read_line(self.player_id, timeout);
+            for order in self.read_orders(request, deadline).await? {
+                self.bot_ctx.log(MatchLogMessage::LateResponse {
+                    player_id: self.player_id,
+                    response: order,
+                });
+            }
+        }
+        let message = format!("{}go", format_classic_state(state)).into_bytes();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let request = self.bot_ctx.request(self.player_id, message, remaining);
+        let orders = self.read_orders(request, deadline).await?;
+        Ok(translate_orders(state, &orders))
+    }
+
+    /// Read orders up to the next `go`, starting with the response to the given request.
+    /// When this fails, the turn stays unfinished.
+    async fn read_orders(
+        &mut self,
+        mut request: Request,
+        deadline: Instant,
+    ) -> RequestResult<Vec<String>> {
+        self.unfinished_turn = true;
+        let mut orders = Vec::new();
+        loop {
+            let response = request.await?;
+            let line = String::from_utf8_lossy(&response).trim().to_string();
+            match line.as_str() {
+                "go" => break,
+                "" => (),
+                _ => orders.push(line),
+            }
+            // the remaining lines are read one by one
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            request = self.bot_ctx.read_line(self.player_id, remaining);
+        }
+        self.unfinished_turn = false;
+        Ok(orders)
+    }
+}
+
+/// Translate orders into an action.
+/// When an order cannot be parsed, the raw orders are returned,
+/// so that they show up as a parse error in the match log.
+fn translate_orders(state: &proto::State, orders: &[String]) -> Vec<u8> {
+    let commands: Option<Vec<proto::Command>> = orders
+        .iter()
+        .map(|order| parse_classic_order(order, state))
+        .collect();
+    match commands {
+        Some(commands) => serde_json::to_vec(&proto::Action {
+            commands,
+            request_id: None,
+        })
+        .unwrap(),
+        None => orders.join("\n").into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planetwars_rules::test_util::test_state;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    /// A bot that answers every request with the next of the given lines,
+    /// and reports the requests it received.
+    struct ScriptedBotSpec {
+        lines: Vec<&'static str>,
+        received: mpsc::UnboundedSender<Option<Vec<u8>>>,
+    }
+
+    struct ScriptedBotHandle {
+        player_id: u32,
+        event_bus: Arc<Mutex<EventBus>>,
+        lines: std::vec::IntoIter<&'static str>,
+        received: mpsc::UnboundedSender<Option<Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl BotSpec for ScriptedBotSpec {
+        async fn run_bot(
+            &self,
+            player_id: u32,
+            event_bus: Arc<Mutex<EventBus>>,
+            _match_logger: MatchLogger,
+        ) -> Result<Box<dyn PlayerHandle>, BotStartError> {
+            Ok(Box::new(ScriptedBotHandle {
+                player_id,
+                event_bus,
+                lines: self.lines.clone().into_iter(),
+                received: self.received.clone(),
+            }))
+        }
+    }
+
+    impl PlayerHandle for ScriptedBotHandle {
+        fn send_request(&mut self, r: RequestMessage) {
+            self.received.send(r.content).unwrap();
+            let line = self.lines.next().expect("script ended");
+            self.event_bus
+                .lock()
+                .unwrap()
+                .resolve_request((self.player_id, r.request_id), Ok(line.into()));
+        }
+
+        fn into_join_handle(self: Box<Self>) -> JoinHandle<()> {
+            tokio::spawn(async {})
+        }
+    }
+
+    #[tokio::test]
+    async fn test_legacy_protocol_turn() {
+        let (received_tx, mut received_rx) = unbounded_channel();
+        let bot_spec = LegacyProtocolBotSpec {
+            bot_spec: Box::new(ScriptedBotSpec {
+                lines: vec!["0 1 5", "", "go"],
+                received: received_tx,
+            }),
+        };
+        let event_bus = Arc::new(Mutex::new(EventBus::new()));
+        let (logger, _log_rx) = unbounded_channel();
+        let handle = bot_spec
+            .run_bot(1, event_bus.clone(), logger.clone())
+            .await
+            .unwrap();
+        let mut players = HashMap::new();
+        players.insert(1, handle);
+        let mut ctx = MatchCtx::new(event_bus, players, logger);
+
+        let state = test_state(&[("a", 0.0, Some(1)), ("b", 0.0, Some(2))]);
+        let request = serde_json::to_vec(&state).unwrap();
+        let response = ctx
+            .request(1, request, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let action: proto::Action = serde_json::from_slice(&response).unwrap();
+        assert_eq!(action.commands.len(), 1);
+        assert_eq!(action.commands[0].origin, "a");
+        assert_eq!(action.commands[0].destination, "b");
+        assert_eq!(action.commands[0].ship_count, 5);
+
+        let state_message = received_rx.recv().await.unwrap();
+        assert_eq!(state_message.unwrap(), b"P 0 0 1 5 1\nP 0 0 2 5 1\ngo");
+        // the remaining lines are read without sending anything
+        assert!(received_rx.recv().await.unwrap().is_none());
+        assert!(received_rx.recv().await.unwrap().is_none());
+
+        // notifications are not passed on to the bot
+        let game_over = proto::ServerMessage::GameOver(proto::GameOver {
+            winner: None,
+            winning_team: None,
+            win_reason: None,
+            turn_num: 1,
+        });
+        let request = serde_json::to_vec(&game_over).unwrap();
+        assert!(ctx
+            .request(1, request, Duration::from_secs(1))
+            .await
+            .is_ok());
+        ctx.shutdown().await;
+        drop(bot_spec);
+        assert!(received_rx.recv().await.is_none());
+    }
+}
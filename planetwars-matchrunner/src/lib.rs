@@ -1,31 +1,103 @@
 pub mod bot_runner;
 pub mod docker_runner;
+pub mod legacy_protocol;
 pub mod match_context;
 pub mod match_log;
 pub mod pw_match;
+pub mod replay;
 
 use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use futures::{stream::FuturesOrdered, StreamExt};
 use match_context::MatchCtx;
-use match_log::{create_log_sink, MatchLogger};
-use planetwars_rules::PwConfig;
+use match_log::{create_log_sink, MatchLogMessage, MatchLogger};
+use planetwars_rules::protocol::ProtocolVersion;
+use planetwars_rules::{
+    CombatRuleConfig, FogOfWar, MapError, MapSource, PlanetWars, PwConfig, RulesVariant, Side,
+    Tiebreak, WinReason,
+};
+use serde::{Deserialize, Serialize};
 
 pub use self::match_context::{EventBus, PlayerHandle};
 
+pub const DEFAULT_MAX_TURNS: u64 = 500;
+pub const DEFAULT_TURN_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How much time bots get to respond.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    /// How long bots get to respond to a message
+    pub turn_timeout: Duration,
+    /// Replaces the turn timeout for the first message a bot has to respond to,
+    /// for bots that take a while to start up.
+    /// This is the game info for bots that receive one, and the first turn otherwise.
+    pub first_turn_timeout: Option<Duration>,
+    /// Extra time bots can spend over the turn timeout, as on a chess clock
+    pub time_bank: Option<TimeBank>,
+}
+
+impl TimeControl {
+    pub fn first_turn_timeout(&self) -> Duration {
+        self.first_turn_timeout.unwrap_or(self.turn_timeout)
+    }
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl {
+            turn_timeout: DEFAULT_TURN_TIMEOUT,
+            first_turn_timeout: None,
+            time_bank: None,
+        }
+    }
+}
+
+/// Time a bot can spend over the turn timeout, shared by all its turns.
+/// Every turn, the time the bot spent over the timeout is taken from its bank,
+/// after which the increment is added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeBank {
+    /// The time in the bank at the start of the match
+    pub initial: Duration,
+    pub increment: Duration,
+}
+
 pub struct MatchConfig {
     pub map_name: String,
-    pub map_path: PathBuf,
+    /// The map or scenario to start the match from
+    pub map: MapSource,
     pub log_path: PathBuf,
     pub players: Vec<MatchPlayer>,
+    pub max_turns: u64,
+    pub time_control: TimeControl,
+    /// Decide the winner when the turn limit is reached, applied in order
+    pub tiebreaks: Vec<Tiebreak>,
+    /// Play by these rules instead of the rules variant of the map
+    pub rules_variant: Option<RulesVariant>,
+    /// Decides which ships survive when fleets of several players meet
+    pub combat_rule: CombatRuleConfig,
+    /// Limits what bots can see of the game, if set
+    pub fog_of_war: Option<FogOfWar>,
+    /// Players that play together, as lists of player numbers.
+    /// Empty when every player plays for themselves.
+    pub teams: Vec<Vec<usize>>,
 }
 
 pub struct MatchPlayer {
     pub bot_spec: Box<dyn BotSpec>,
+    /// Name of the player, which is shared with the other bots
+    pub name: Option<String>,
+    pub protocol: ProtocolVersion,
+    /// Tell the bot which of its commands were rejected in the previous turn,
+    /// in a `previous_turn` field of the turn message.
+    pub report_command_errors: bool,
 }
 
 #[async_trait]
@@ -35,31 +107,97 @@ pub trait BotSpec: Send + Sync {
         player_id: u32,
         event_bus: Arc<Mutex<EventBus>>,
         match_logger: MatchLogger,
-    ) -> Box<dyn PlayerHandle>;
+    ) -> Result<Box<dyn PlayerHandle>, BotStartError>;
+}
+
+/// Why a bot could not be started.
+/// A player whose bot does not start forfeits the match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BotStartError {
+    /// The docker image of the bot could not be pulled
+    ImagePullFailed { error: String },
+    /// The docker container of the bot could not be created or started
+    ContainerCreateFailed { error: String },
+    /// The bot process could not be spawned
+    SpawnFailed { error: String },
+    /// A remote bot did not connect in time
+    NotConnected,
+}
+
+impl fmt::Display for BotStartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BotStartError::ImagePullFailed { error } => {
+                write!(f, "could not pull image: {}", error)
+            }
+            BotStartError::ContainerCreateFailed { error } => {
+                write!(f, "could not create container: {}", error)
+            }
+            BotStartError::SpawnFailed { error } => write!(f, "could not start bot: {}", error),
+            BotStartError::NotConnected => write!(f, "bot did not connect"),
+        }
+    }
 }
 
+impl std::error::Error for BotStartError {}
+
+#[derive(Debug)]
 pub struct MatchOutcome {
+    /// The winning player, when the match was not won by a team
     pub winner: Option<usize>,
+    /// The winning team, numbered from 1 in the order of `MatchConfig::teams`
+    pub winning_team: Option<usize>,
+    /// Why the winner won, set when there is a winning player or team
+    pub win_reason: Option<WinReason>,
+    /// The amount of turns that were played
+    pub num_turns: u64,
     pub player_outcomes: Vec<PlayerOutcome>,
 }
 
+#[derive(Debug)]
 pub struct PlayerOutcome {
     pub had_errors: bool,
     pub crashed: bool,
+    /// Set when the player forfeited because its bot could not be started
+    pub forfeit: Option<BotStartError>,
+}
+
+/// Reasons a match could not be played
+#[derive(Debug)]
+pub enum MatchError {
+    InvalidMap(MapError),
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchError::InvalidMap(err) => write!(f, "invalid map: {}", err),
+        }
+    }
 }
 
-pub async fn run_match(config: MatchConfig) -> MatchOutcome {
+impl std::error::Error for MatchError {}
+
+pub async fn run_match(config: MatchConfig) -> Result<MatchOutcome, MatchError> {
     let pw_config = PwConfig {
-        map_file: config.map_path,
-        max_turns: 500,
+        map: config.map,
+        max_turns: config.max_turns,
+        combat_rule: config.combat_rule,
+        fog_of_war: config.fog_of_war,
+        tiebreaks: config.tiebreaks,
+        variant: config.rules_variant,
+        teams: config.teams,
     };
+    // load the map before starting any bots
+    let mut match_state =
+        PlanetWars::create(pw_config, config.players.len()).map_err(MatchError::InvalidMap)?;
 
     let event_bus = Arc::new(Mutex::new(EventBus::new()));
-    let match_logger = create_log_sink(&config.log_path).await;
+    let (match_logger, log_sink) = create_log_sink(&config.log_path).await;
 
     // start bots
-    // TODO: what happens when a bot fails?
-    let players = config
+    let started_bots: Vec<_> = config
         .players
         .iter()
         .enumerate()
@@ -77,33 +215,78 @@ pub async fn run_match(config: MatchConfig) -> MatchOutcome {
         .collect()
         .await;
 
-    let match_ctx = MatchCtx::new(event_bus, players, match_logger);
+    let mut players = HashMap::new();
+    let mut forfeits = BTreeMap::new();
+    for (player_id, started_bot) in started_bots {
+        match started_bot {
+            Ok(player_handle) => {
+                players.insert(player_id, player_handle);
+            }
+            Err(reason) => {
+                match_state.forfeit(player_id as usize);
+                forfeits.insert(player_id, reason);
+            }
+        }
+    }
 
-    let mut match_instance = pw_match::PwMatch::create(match_ctx, pw_config);
+    let mut match_ctx = MatchCtx::new(event_bus, players, match_logger);
+    for (&player_id, reason) in forfeits.iter() {
+        match_ctx.log(MatchLogMessage::Forfeit {
+            player_id,
+            reason: reason.clone(),
+        });
+    }
+    let player_settings = config
+        .players
+        .iter()
+        .map(|player| pw_match::PlayerSettings {
+            name: player.name.clone(),
+            protocol: player.protocol,
+            report_command_errors: player.report_command_errors,
+        })
+        .collect();
+
+    let mut match_instance =
+        pw_match::PwMatch::create(match_ctx, match_state, player_settings, config.time_control);
     match_instance.run().await;
+    let victory = match_instance.victory();
+    let num_turns = match_instance.match_state.state().turn_num;
     match_instance.match_ctx.shutdown().await;
-
-    let survivors = match_instance.match_state.state().living_players();
-    let winner = if survivors.len() == 1 {
-        Some(survivors[0])
-    } else {
-        None
-    };
+    // the bots and the match context have dropped their loggers now,
+    // so this waits for the remaining messages to be written
+    let _ = log_sink.await;
 
     let player_outcomes = (1..=config.players.len())
-        .map(|player_id| {
-            let player_status = &match_instance.player_status[&player_id];
-            PlayerOutcome {
-                had_errors: player_status.had_command_errors,
-                crashed: player_status.terminated,
+        .map(|player_id| match forfeits.remove(&(player_id as u32)) {
+            Some(reason) => PlayerOutcome {
+                had_errors: false,
+                crashed: false,
+                forfeit: Some(reason),
+            },
+            None => {
+                let player_status = &match_instance.player_status[&player_id];
+                PlayerOutcome {
+                    had_errors: player_status.had_command_errors,
+                    crashed: player_status.terminated,
+                    forfeit: None,
+                }
             }
         })
         .collect();
 
-    MatchOutcome {
-        winner,
+    Ok(MatchOutcome {
+        winner: match victory.map(|victory| victory.winner) {
+            Some(Side::Player(player_id)) => Some(player_id),
+            _ => None,
+        },
+        winning_team: match victory.map(|victory| victory.winner) {
+            Some(Side::Team(team)) => Some(team),
+            _ => None,
+        },
+        win_reason: victory.map(|victory| victory.reason),
+        num_turns,
         player_outcomes,
-    }
+    })
 }
 
 // writing this as a closure causes lifetime inference errors
@@ -112,7 +295,7 @@ async fn start_bot(
     event_bus: Arc<Mutex<EventBus>>,
     bot_spec: &dyn BotSpec,
     match_logger: MatchLogger,
-) -> (u32, Box<dyn PlayerHandle>) {
+) -> (u32, Result<Box<dyn PlayerHandle>, BotStartError>) {
     let player_handle = bot_spec.run_bot(player_id, event_bus, match_logger).await;
     (player_id, player_handle)
 }
@@ -15,7 +15,64 @@ use crate::match_log::{MatchLogMessage, MatchLogger};
 pub struct RequestMessage {
     pub request_id: u32,
     pub timeout: Duration,
-    pub content: Vec<u8>,
+    /// Line to write to the bot before reading its response.
+    /// When not set, the response is read without writing anything.
+    pub content: Option<Vec<u8>>,
+}
+
+/// Requests a bot did not respond to in time.
+/// Bots answer requests in order, so the responses to these requests
+/// still have to be read before the response to the current request.
+#[derive(Debug, Default)]
+pub struct UnansweredRequests {
+    count: usize,
+}
+
+impl UnansweredRequests {
+    /// Record that the given request timed out.
+    /// A read-only request continues the response to an earlier request,
+    /// so the bot does not owe a separate response to it.
+    pub fn record_timeout(&mut self, request: &RequestMessage) {
+        if request.content.is_some() {
+            self.count += 1;
+        }
+    }
+
+    /// Record a response to an earlier request
+    pub fn record_late_response(&mut self) {
+        self.count = self.count.saturating_sub(1);
+    }
+
+    /// Whether the given line responds to an earlier request than the given one.
+    /// When the bot echoed the id of the request it responds to, that id decides;
+    /// otherwise the line answers the oldest request that is still unanswered.
+    /// Read-only requests receive late lines too, because their sender
+    /// reads the rest of the responses to earlier requests itself.
+    pub fn is_late_response(&mut self, request: &RequestMessage, line: &[u8]) -> bool {
+        if request.content.is_none() {
+            self.record_late_response();
+            return false;
+        }
+        let is_late = match echoed_request_id(line) {
+            Some(request_id) => request_id < request.request_id,
+            None => self.count > 0,
+        };
+        if is_late {
+            self.record_late_response();
+        }
+        is_late
+    }
+}
+
+/// The `request_id` a bot echoed in its response, if it did
+fn echoed_request_id(line: &[u8]) -> Option<u32> {
+    #[derive(Deserialize)]
+    struct EchoedRequestId {
+        request_id: Option<u32>,
+    }
+    serde_json::from_slice::<EchoedRequestId>(line)
+        .ok()
+        .and_then(|echoed| echoed.request_id)
 }
 
 pub struct MatchCtx {
@@ -48,6 +105,20 @@ impl MatchCtx {
 
     // TODO: implement a clean way to handle the player not existing
     pub fn request(&mut self, player_id: u32, content: Vec<u8>, timeout: Duration) -> Request {
+        self.send_request(player_id, Some(content), timeout)
+    }
+
+    /// Read the next line a player writes, without sending it anything
+    pub fn read_line(&mut self, player_id: u32, timeout: Duration) -> Request {
+        self.send_request(player_id, None, timeout)
+    }
+
+    fn send_request(
+        &mut self,
+        player_id: u32,
+        content: Option<Vec<u8>>,
+        timeout: Duration,
+    ) -> Request {
         let player = self.players.get_mut(&player_id).unwrap();
         let request_id = player.request_ctr;
         player.request_ctr += 1;
@@ -65,6 +136,11 @@ impl MatchCtx {
         }
     }
 
+    /// The id the next request to the given player will get
+    pub fn next_request_id(&self, player_id: u32) -> u32 {
+        self.players[&player_id].request_ctr
+    }
+
     pub fn players(&self) -> Vec<u32> {
         self.players.keys().cloned().collect()
     }
@@ -167,3 +243,59 @@ pub enum RequestError {
 }
 
 pub type RequestResult<T> = Result<T, RequestError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(request_id: u32) -> RequestMessage {
+        RequestMessage {
+            request_id,
+            timeout: Duration::from_secs(1),
+            content: Some(b"state".to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_echoed_late_response() {
+        let mut unanswered = UnansweredRequests::default();
+        let request = request(3);
+        assert!(unanswered.is_late_response(&request, br#"{"request_id": 2, "moves": []}"#));
+        assert!(!unanswered.is_late_response(&request, br#"{"request_id": 3, "moves": []}"#));
+        // without unanswered requests, lines respond to the current request
+        assert!(!unanswered.is_late_response(&request, br#"{"moves": []}"#));
+        assert!(!unanswered.is_late_response(&request, b"go"));
+    }
+
+    #[test]
+    fn test_unanswered_late_response() {
+        let mut unanswered = UnansweredRequests::default();
+        unanswered.record_timeout(&request(0));
+        unanswered.record_timeout(&request(1));
+        let request = request(2);
+        assert!(unanswered.is_late_response(&request, b"first"));
+        assert!(unanswered.is_late_response(&request, b"second"));
+        assert!(!unanswered.is_late_response(&request, b"third"));
+
+        // an echoed id for the current request is never late
+        unanswered.record_timeout(&request);
+        assert!(!unanswered.is_late_response(&request, br#"{"request_id": 2}"#));
+    }
+
+    #[test]
+    fn test_read_only_late_response() {
+        let mut unanswered = UnansweredRequests::default();
+        let read_only = RequestMessage {
+            content: None,
+            ..request(1)
+        };
+        // the bot does not owe a response to a read-only request
+        unanswered.record_timeout(&read_only);
+        assert!(!unanswered.is_late_response(&request(2), b"first"));
+
+        // a read-only request receives the late line itself
+        unanswered.record_timeout(&request(2));
+        assert!(!unanswered.is_late_response(&read_only, b"late"));
+        assert!(!unanswered.is_late_response(&request(3), b"current"));
+    }
+}
@@ -1,24 +1,59 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead};
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use tokio::{fs::File, io::AsyncWriteExt};
 
-use planetwars_rules::protocol::State;
+use planetwars_rules::protocol::{Expedition, Planet, State};
+use planetwars_rules::{CombatRuleConfig, FogOfWar, RulesVariant, TurnEvent};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 use crate::pw_match::PlayerCommand;
+use crate::BotStartError;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum MatchLogMessage {
+    /// The rules variant the match is played by, logged before the initial state
+    #[serde(rename = "rules_variant")]
+    RulesVariant(RulesVariant),
+    /// The combat rule the match is played by, logged after the rules variant
+    #[serde(rename = "combat_rule")]
+    CombatRule { rule: CombatRuleConfig },
+    /// What players could see, logged after the combat rule
+    /// when the match was played with fog of war
+    #[serde(rename = "fog_of_war")]
+    FogOfWar(FogOfWar),
     #[serde(rename = "gamestate")]
     GameState(State),
+    /// A gamestate, stored as the changes to the previous gamestate.
+    /// Only found in log files; `read_log` expands these to `GameState`s.
+    #[serde(rename = "gamestate_delta")]
+    GameStateDelta(StateDelta),
+    /// What happened while resolving a turn, logged before the resulting state
+    #[serde(rename = "turn_events")]
+    TurnEvents {
+        turn_num: u64,
+        events: Vec<TurnEvent>,
+    },
     #[serde(rename = "stderr")]
     StdErr(StdErrMessage),
+    /// The bot of a player could not be started, so it forfeited the match
+    #[serde(rename = "forfeit")]
+    Forfeit {
+        player_id: u32,
+        reason: BotStartError,
+    },
     #[serde(rename = "bot_terminated")]
     BotTerminated { player_id: u32 },
     #[serde(rename = "timeout")]
     Timeout { player_id: u32 },
+    /// A line a bot wrote in response to an earlier request, which probably timed out.
+    /// It is discarded, so that the bot does not get out of sync.
+    #[serde(rename = "late_response")]
+    LateResponse { player_id: u32, response: String },
     #[serde(rename = "bad_command")]
     BadCommand {
         player_id: u32,
@@ -38,19 +73,220 @@ pub struct StdErrMessage {
     pub message: String,
 }
 
+/// The changes between two consecutive gamestates, referring to planets by index.
+/// Planets only change owner and ship count, and most expeditions just travel
+/// one more turn, so these are a lot smaller than the states themselves.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateDelta {
+    /// The planets that changed, as (planet index, owner, ship count)
+    pub planets: Vec<(usize, Option<usize>, u64)>,
+    /// All expeditions of the new state, in order
+    pub expeditions: Vec<ExpeditionDelta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ExpeditionDelta {
+    /// The expedition with this id in the previous state, which traveled one more turn
+    Continued(u64),
+    New(CompactExpedition),
+}
+
+/// An expedition, referring to planets by index
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompactExpedition {
+    pub id: u64,
+    pub ship_count: u64,
+    pub origin: usize,
+    pub destination: usize,
+    pub owner: usize,
+    pub turns_remaining: u64,
+}
+
+/// A full gamestate is written at least once every this many states,
+/// so that a log can be inspected without expanding it from the start.
+const KEYFRAME_INTERVAL: usize = 50;
+
+impl StateDelta {
+    /// Describe `state` as changes to `previous`.
+    /// Returns `None` when the planets themselves changed, which requires a full state.
+    pub fn between(previous: &State, state: &State) -> Option<StateDelta> {
+        if previous.planets.len() != state.planets.len() || previous.teams != state.teams {
+            return None;
+        }
+        let mut planets = Vec::new();
+        for (index, (prev, planet)) in previous.planets.iter().zip(&state.planets).enumerate() {
+            if fixed_planet_fields(prev) != fixed_planet_fields(planet) {
+                return None;
+            }
+            if prev.owner != planet.owner || prev.ship_count != planet.ship_count {
+                planets.push((index, planet.owner, planet.ship_count));
+            }
+        }
+
+        let planet_indices = planet_indices(state);
+        let previous_expeditions: HashMap<u64, &Expedition> =
+            previous.expeditions.iter().map(|e| (e.id, e)).collect();
+        let mut expeditions = Vec::with_capacity(state.expeditions.len());
+        for exp in state.expeditions.iter() {
+            let continued = previous_expeditions.get(&exp.id).is_some_and(|prev| {
+                let traveled = Expedition {
+                    turns_remaining: prev.turns_remaining.saturating_sub(1),
+                    ..(*prev).clone()
+                };
+                traveled == *exp
+            });
+            if continued {
+                expeditions.push(ExpeditionDelta::Continued(exp.id));
+            } else {
+                expeditions.push(ExpeditionDelta::New(CompactExpedition {
+                    id: exp.id,
+                    ship_count: exp.ship_count,
+                    origin: *planet_indices.get(exp.origin.as_str())?,
+                    destination: *planet_indices.get(exp.destination.as_str())?,
+                    owner: exp.owner,
+                    turns_remaining: exp.turns_remaining,
+                }));
+            }
+        }
+        Some(StateDelta {
+            planets,
+            expeditions,
+        })
+    }
+
+    /// Reconstruct the state this delta was made from.
+    /// Returns `None` when the delta refers to planets or expeditions
+    /// that do not exist in `previous`.
+    pub fn apply(&self, previous: &State) -> Option<State> {
+        let mut state = previous.clone();
+        for &(index, owner, ship_count) in self.planets.iter() {
+            let planet = state.planets.get_mut(index)?;
+            planet.owner = owner;
+            planet.ship_count = ship_count;
+        }
+
+        let planet_name = |index: usize| previous.planets.get(index).map(|p| p.name.clone());
+        state.expeditions = Vec::with_capacity(self.expeditions.len());
+        for exp in self.expeditions.iter() {
+            let expedition = match exp {
+                ExpeditionDelta::Continued(id) => {
+                    let prev = previous.expeditions.iter().find(|e| e.id == *id)?;
+                    Expedition {
+                        turns_remaining: prev.turns_remaining.saturating_sub(1),
+                        ..prev.clone()
+                    }
+                }
+                ExpeditionDelta::New(exp) => Expedition {
+                    id: exp.id,
+                    ship_count: exp.ship_count,
+                    origin: planet_name(exp.origin)?,
+                    destination: planet_name(exp.destination)?,
+                    owner: exp.owner,
+                    turns_remaining: exp.turns_remaining,
+                },
+            };
+            state.expeditions.push(expedition);
+        }
+        Some(state)
+    }
+}
+
+/// The parts of a planet that do not change during a game
+fn fixed_planet_fields(planet: &Planet) -> (&str, f64, f64, u64, bool) {
+    (
+        planet.name.as_str(),
+        planet.x,
+        planet.y,
+        planet.growth_rate,
+        planet.hidden,
+    )
+}
+
+fn planet_indices(state: &State) -> HashMap<&str, usize> {
+    state
+        .planets
+        .iter()
+        .enumerate()
+        .map(|(index, planet)| (planet.name.as_str(), index))
+        .collect()
+}
+
+/// Writes gamestates as deltas to the previous state, with a full keyframe
+/// every `KEYFRAME_INTERVAL` states.
+#[derive(Default)]
+struct StateEncoder {
+    previous: Option<State>,
+    states_since_keyframe: usize,
+}
+
+impl StateEncoder {
+    fn encode(&mut self, message: MatchLogMessage) -> MatchLogMessage {
+        let state = match message {
+            MatchLogMessage::GameState(state) => state,
+            message => return message,
+        };
+        let delta = match &self.previous {
+            Some(previous) if self.states_since_keyframe + 1 < KEYFRAME_INTERVAL => {
+                StateDelta::between(previous, &state)
+            }
+            _ => None,
+        };
+        self.previous = Some(state.clone());
+        match delta {
+            Some(delta) => {
+                self.states_since_keyframe += 1;
+                MatchLogMessage::GameStateDelta(delta)
+            }
+            None => {
+                self.states_since_keyframe = 0;
+                MatchLogMessage::GameState(state)
+            }
+        }
+    }
+}
+
+/// Expands gamestate deltas back to full gamestates.
+#[derive(Default)]
+struct StateDecoder {
+    previous: Option<State>,
+}
+
+impl StateDecoder {
+    fn decode(&mut self, message: MatchLogMessage) -> io::Result<MatchLogMessage> {
+        let state = match message {
+            MatchLogMessage::GameState(state) => state,
+            MatchLogMessage::GameStateDelta(delta) => self
+                .previous
+                .as_ref()
+                .and_then(|previous| delta.apply(previous))
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid gamestate delta")
+                })?,
+            message => return Ok(message),
+        };
+        self.previous = Some(state.clone());
+        Ok(MatchLogMessage::GameState(state))
+    }
+}
+
 pub type MatchLogger = mpsc::UnboundedSender<MatchLogMessage>;
 
-pub async fn create_log_sink(log_file_path: &Path) -> MatchLogger {
+/// Start writing the match log. The returned task finishes once every logger
+/// has been dropped and all messages have been written.
+pub async fn create_log_sink(log_file_path: &Path) -> (MatchLogger, JoinHandle<()>) {
     let (tx, rx) = mpsc::unbounded_channel();
     let log_file = File::create(log_file_path)
         .await
         .expect("Could not create log file");
-    tokio::spawn(run_log_sink(rx, log_file));
-    tx
+    let sink = tokio::spawn(run_log_sink(rx, log_file));
+    (tx, sink)
 }
 
 async fn run_log_sink(mut rx: mpsc::UnboundedReceiver<MatchLogMessage>, mut file: File) {
+    let mut encoder = StateEncoder::default();
     while let Some(message) = rx.recv().await {
+        let message = encoder.encode(message);
         let json = serde_json::to_string(&message).expect("failed to serialize message");
         file.write_all(json.as_bytes())
             .await
@@ -60,3 +296,107 @@ async fn run_log_sink(mut rx: mpsc::UnboundedReceiver<MatchLogMessage>, mut file
             .expect("failed to write newline log message to file");
     }
 }
+
+/// Parse a match log, as written by the log sink.
+/// Gamestate deltas are expanded to full gamestates, so that logs that were
+/// written before deltas existed read the same.
+pub fn read_log<R: BufRead>(reader: R) -> io::Result<Vec<MatchLogMessage>> {
+    let mut decoder = StateDecoder::default();
+    let mut messages = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        messages.push(decoder.decode(serde_json::from_str(&line)?)?);
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planetwars_rules::protocol as proto;
+    use planetwars_rules::test_util::test_state;
+    use planetwars_rules::PlanetWars;
+
+    fn play_game(num_turns: usize) -> Vec<State> {
+        let initial = test_state(&[("a", -6.0, Some(1)), ("b", 0.0, None), ("c", 6.0, Some(2))]);
+        let mut game = PlanetWars::from_state(&initial, 2, 1000).unwrap();
+        let mut states = vec![initial];
+        for turn in 0..num_turns {
+            let command = |origin: &str| proto::Command {
+                origin: origin.to_string(),
+                destination: "b".to_string(),
+                ship_count: 1,
+            };
+            if turn % 3 == 0 {
+                game.play_turn(&[(1, command("a")), (2, command("c"))]);
+            } else {
+                game.play_turn(&[]);
+            }
+            states.push(game.serialize_state());
+        }
+        states
+    }
+
+    fn write_log(messages: Vec<MatchLogMessage>) -> String {
+        let mut encoder = StateEncoder::default();
+        messages
+            .into_iter()
+            .map(|message| serde_json::to_string(&encoder.encode(message)).unwrap() + "\n")
+            .collect()
+    }
+
+    fn logged_states(log: Vec<MatchLogMessage>) -> Vec<State> {
+        log.into_iter()
+            .filter_map(|message| match message {
+                MatchLogMessage::GameState(state) => Some(state),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_delta_log_roundtrip() {
+        let states = play_game(120);
+        let mut messages = vec![MatchLogMessage::Timeout { player_id: 1 }];
+        messages.extend(states.iter().cloned().map(MatchLogMessage::GameState));
+        let log = write_log(messages);
+
+        let keyframes = log.lines().filter(|l| l.contains(r#""gamestate""#)).count();
+        let deltas = log
+            .lines()
+            .filter(|l| l.contains("gamestate_delta"))
+            .count();
+        assert_eq!(keyframes, 3);
+        assert_eq!(deltas, states.len() - 3);
+
+        let read = read_log(log.as_bytes()).unwrap();
+        assert!(matches!(read[0], MatchLogMessage::Timeout { player_id: 1 }));
+        assert_eq!(logged_states(read), states);
+    }
+
+    #[test]
+    fn test_read_full_state_log() {
+        let states = play_game(10);
+        let log: String = states
+            .iter()
+            .map(|state| {
+                let message = MatchLogMessage::GameState(state.clone());
+                serde_json::to_string(&message).unwrap() + "\n"
+            })
+            .collect();
+        assert_eq!(logged_states(read_log(log.as_bytes()).unwrap()), states);
+    }
+
+    #[test]
+    fn test_delta_without_previous_state() {
+        let delta = MatchLogMessage::GameStateDelta(StateDelta {
+            planets: Vec::new(),
+            expeditions: Vec::new(),
+        });
+        let log = serde_json::to_string(&delta).unwrap();
+        assert!(read_log(log.as_bytes()).is_err());
+    }
+}
@@ -2,7 +2,7 @@ use crate::match_context::RequestError;
 use crate::match_log::MatchLogMessage;
 
 use super::match_context::{MatchCtx, RequestResult};
-use futures::stream::futures_unordered::FuturesUnordered;
+use futures::stream::{FuturesOrdered, FuturesUnordered};
 use futures::{FutureExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use tokio::time::Duration;
@@ -11,17 +11,35 @@ use serde_json;
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::time::Instant;
 
 pub use planetwars_rules::config::{Config, Map};
 
-use planetwars_rules::protocol as proto;
-use planetwars_rules::serializer as pw_serializer;
-use planetwars_rules::{PlanetWars, PwConfig};
+use planetwars_rules::protocol::{self as proto, ProtocolVersion};
+use planetwars_rules::{PlanetWars, Side, TurnEvent, Victory};
+
+use crate::TimeControl;
 
 pub struct PwMatch {
     pub match_ctx: MatchCtx,
     pub match_state: PlanetWars,
     pub player_status: HashMap<usize, PlayerStatus>,
+    players: Vec<PlayerSettings>,
+    time_control: TimeControl,
+    /// Time left in the time bank of every player, when there are time banks
+    time_banks: HashMap<usize, Duration>,
+    /// Whether the first turn is still to be played
+    first_turn: bool,
+    /// Problems with the last response of each player
+    command_feedback: HashMap<usize, proto::CommandFeedback>,
+}
+
+/// Settings for a player, in order of player id.
+pub struct PlayerSettings {
+    pub name: Option<String>,
+    pub protocol: ProtocolVersion,
+    /// Whether to tell the player about its rejected commands
+    pub report_command_errors: bool,
 }
 
 pub struct PlayerStatus {
@@ -30,9 +48,12 @@ pub struct PlayerStatus {
 }
 
 impl PwMatch {
-    pub fn create(match_ctx: MatchCtx, config: PwConfig) -> Self {
-        // TODO: this is kind of hacked together at the moment
-        let match_state = PlanetWars::create(config, match_ctx.players().len());
+    pub fn create(
+        match_ctx: MatchCtx,
+        match_state: PlanetWars,
+        players: Vec<PlayerSettings>,
+        time_control: TimeControl,
+    ) -> Self {
         let player_status = match_ctx
             .players()
             .into_iter()
@@ -46,15 +67,47 @@ impl PwMatch {
                 )
             })
             .collect();
+        let time_banks = match &time_control.time_bank {
+            Some(time_bank) => (1..=players.len())
+                .map(|player_id| (player_id, time_bank.initial))
+                .collect(),
+            None => HashMap::new(),
+        };
 
         PwMatch {
             match_state,
             match_ctx,
             player_status,
+            players,
+            time_banks,
+            time_control,
+            first_turn: true,
+            command_feedback: HashMap::new(),
         }
     }
 
+    /// Play the match until it is finished.
+    ///
+    /// Turns are simultaneous: all living players are prompted at the same time,
+    /// and their actions are only applied once every player has responded or timed out.
+    /// The actions are then applied in order of player number, so the outcome of a turn,
+    /// including expedition ids and the order of log messages, does not depend on
+    /// response timing. No player gets to see or react to the actions of others
+    /// before the next turn.
     pub async fn run(&mut self) {
+        self.send_game_info().await;
+
+        self.match_ctx.log(MatchLogMessage::RulesVariant(
+            self.match_state.rules_variant().clone(),
+        ));
+        if let Some(rule) = self.match_state.state().combat_rule.config() {
+            self.match_ctx.log(MatchLogMessage::CombatRule { rule });
+        }
+        // the log holds the full states, from which the views of players can be rebuilt
+        if let Some(fog_of_war) = self.match_state.fog_of_war() {
+            self.match_ctx
+                .log(MatchLogMessage::FogOfWar(fog_of_war.clone()));
+        }
         // log initial state
         self.log_game_state();
 
@@ -64,40 +117,218 @@ impl PwMatch {
             for (player_id, turn) in player_messages {
                 let player_action = self.execute_action(player_id, turn);
                 self.update_player_status(player_id, &player_action);
+                self.command_feedback
+                    .insert(player_id, command_feedback(&player_action));
                 self.log_player_action(player_id, player_action);
             }
-            self.match_state.step();
+            let events = self.match_state.step();
+            self.log_turn_events(events);
             self.log_game_state();
         }
+
+        self.send_game_over().await;
     }
 
-    async fn prompt_players(&mut self) -> Vec<(usize, RequestResult<Vec<u8>>)> {
-        // borrow these outside closure to make the borrow checker happy
-        let state = self.match_state.state();
-        let match_ctx = &mut self.match_ctx;
+    fn protocol(&self, player_id: usize) -> ProtocolVersion {
+        self.players[player_id - 1].protocol
+    }
+
+    /// Send a message to all players that use protocol V2 or later and have not terminated,
+    /// and wait for their acknowledgements.
+    async fn notify_players<F>(&mut self, timeout: Duration, make_message: F)
+    where
+        F: Fn(&PlanetWars, usize) -> proto::ServerMessage,
+    {
+        let player_ids: Vec<usize> = self
+            .player_status
+            .iter()
+            .filter(|(&player_id, status)| {
+                !status.terminated && self.protocol(player_id) >= ProtocolVersion::V2
+            })
+            .map(|(&player_id, _)| player_id)
+            .collect();
+
+        let messages: Vec<(usize, Vec<u8>)> = player_ids
+            .into_iter()
+            .map(|player_id| {
+                let message = make_message(&self.match_state, player_id);
+                let mut message = serde_json::to_value(&message).unwrap();
+                self.tag_request(player_id, &mut message);
+                (player_id, serde_json::to_vec(&message).unwrap())
+            })
+            .collect();
+
+        let requests = messages
+            .into_iter()
+            .map(|(player_id, message)| {
+                self.match_ctx
+                    .request(player_id.try_into().unwrap(), message, timeout)
+            })
+            .collect::<FuturesUnordered<_>>();
+        // the content of the acknowledgements does not matter
+        let _ = requests.collect::<Vec<_>>().await;
+    }
+
+    async fn send_game_info(&mut self) {
+        let names: Vec<Option<String>> = self.players.iter().map(|p| p.name.clone()).collect();
+        let turn_timeout = self.time_control.turn_timeout;
+        // this is the first message bots that receive it have to respond to
+        let timeout = self.time_control.first_turn_timeout();
+        self.notify_players(timeout, |match_state, player_id| {
+            let num_players = names.len();
+            // rotate so that the receiving player comes first, like in the gamestate
+            let players = (0..num_players)
+                .map(|offset| names[(player_id - 1 + offset) % num_players].clone())
+                .collect();
+            proto::ServerMessage::GameInfo(proto::GameInfo {
+                player_num: player_id,
+                players,
+                max_turns: match_state.state().max_turns,
+                turn_timeout_ms: turn_timeout.as_millis() as u64,
+            })
+        })
+        .await;
+    }
+
+    /// The winning player or team and why they won, if there is one
+    pub fn victory(&self) -> Option<Victory> {
+        self.match_state.victory()
+    }
+
+    async fn send_game_over(&mut self) {
+        let victory = self.victory();
+        let timeout = self.time_control.turn_timeout;
+        self.notify_players(timeout, |match_state, player_id| {
+            proto::ServerMessage::GameOver(proto::GameOver {
+                winner: match victory.map(|victory| victory.winner) {
+                    Some(Side::Player(winner)) => {
+                        Some(match_state.rotated_player_num(player_id, winner))
+                    }
+                    _ => None,
+                },
+                winning_team: match victory.map(|victory| victory.winner) {
+                    Some(Side::Team(team)) => Some(team),
+                    _ => None,
+                },
+                win_reason: victory.map(|victory| victory.reason),
+                turn_num: match_state.state().turn_num,
+            })
+        })
+        .await;
+    }
 
+    async fn prompt_players(&mut self) -> Vec<(usize, RequestResult<Vec<u8>>)> {
         // TODO: this numbering is really messy.
         // Get rid of the distinction between player_num
         // and player_id.
-
-        self.match_state
+        let messages: Vec<(usize, Vec<u8>, Duration, Duration)> = self
+            .match_state
             .state()
             .players
             .iter()
             .filter(|p| p.alive)
-            .map(move |player| {
-                let state_for_player = pw_serializer::serialize_rotated(state, player.id - 1);
+            .map(|player| {
+                let base_timeout = self.base_timeout(player.id);
+                let timeout = base_timeout + self.time_bank(player.id);
+                let message = self.turn_message(player.id, timeout);
+                (player.id, message, timeout, base_timeout)
+            })
+            .collect();
+
+        // borrow this outside closure to make the borrow checker happy
+        let match_ctx = &mut self.match_ctx;
+        let start = Instant::now();
+        let responses = messages
+            .into_iter()
+            .map(move |(player_id, message, timeout, base_timeout)| {
                 match_ctx
-                    .request(
-                        player.id.try_into().unwrap(),
-                        serde_json::to_vec(&state_for_player).unwrap(),
-                        Duration::from_millis(1000),
-                    )
-                    .map(move |resp| (player.id, resp))
+                    .request(player_id.try_into().unwrap(), message, timeout)
+                    .map(move |resp| {
+                        let overtime = start.elapsed().saturating_sub(base_timeout);
+                        (player_id, resp, overtime)
+                    })
             })
-            .collect::<FuturesUnordered<_>>()
+            // responses are returned in player order, regardless of when they arrived
+            .collect::<FuturesOrdered<_>>()
             .collect::<Vec<_>>()
-            .await
+            .await;
+
+        self.first_turn = false;
+        responses
+            .into_iter()
+            .map(|(player_id, resp, overtime)| {
+                self.charge_time_bank(player_id, overtime);
+                (player_id, resp)
+            })
+            .collect()
+    }
+
+    /// How long the given player gets to respond to the next turn,
+    /// before it starts using its time bank
+    fn base_timeout(&self, player_id: usize) -> Duration {
+        // bots that do not receive game info start with their first turn
+        if self.first_turn && self.protocol(player_id) < ProtocolVersion::V2 {
+            self.time_control.first_turn_timeout()
+        } else {
+            self.time_control.turn_timeout
+        }
+    }
+
+    fn time_bank(&self, player_id: usize) -> Duration {
+        self.time_banks.get(&player_id).copied().unwrap_or_default()
+    }
+
+    /// Take the time the player spent over its timeout from its time bank,
+    /// and add the increment for the next turn.
+    fn charge_time_bank(&mut self, player_id: usize, overtime: Duration) {
+        let time_bank = match &self.time_control.time_bank {
+            Some(time_bank) => time_bank,
+            None => return,
+        };
+        let remaining = self.time_banks.entry(player_id).or_default();
+        *remaining = remaining.saturating_sub(overtime) + time_bank.increment;
+    }
+
+    /// The message that prompts a player for its next move
+    fn turn_message(&self, player_id: usize, timeout: Duration) -> Vec<u8> {
+        let settings = &self.players[player_id - 1];
+        let state = self.match_state.serialize_player_state(player_id);
+        let mut message = match settings.protocol {
+            ProtocolVersion::V1 => serde_json::to_value(&state),
+            ProtocolVersion::V2 => serde_json::to_value(proto::ServerMessage::State(state)),
+            ProtocolVersion::V3 => {
+                let turn_state = proto::TurnState {
+                    state,
+                    turn_num: self.match_state.state().turn_num,
+                    max_turns: self.match_state.state().max_turns,
+                    deadline_ms: timeout.as_millis() as u64,
+                };
+                serde_json::to_value(proto::ServerMessage::Turn(turn_state))
+            }
+        }
+        .unwrap();
+
+        self.tag_request(player_id, &mut message);
+        if settings.report_command_errors {
+            let feedback = self
+                .command_feedback
+                .get(&player_id)
+                .cloned()
+                .unwrap_or_default();
+            message["previous_turn"] = serde_json::to_value(&feedback).unwrap();
+        }
+        serde_json::to_vec(&message).unwrap()
+    }
+
+    /// Tell bots that use protocol V3 the id of the request they receive,
+    /// so that they can echo it in their response.
+    fn tag_request(&self, player_id: usize, message: &mut serde_json::Value) {
+        if self.protocol(player_id) >= ProtocolVersion::V3 {
+            let request_id = self
+                .match_ctx
+                .next_request_id(player_id.try_into().unwrap());
+            message["request_id"] = request_id.into();
+        }
     }
 
     fn execute_action(&mut self, player_num: usize, turn: RequestResult<Vec<u8>>) -> PlayerAction {
@@ -132,6 +363,12 @@ impl PwMatch {
         self.match_ctx.log(MatchLogMessage::GameState(state));
     }
 
+    fn log_turn_events(&mut self, events: Vec<TurnEvent>) {
+        let turn_num = self.match_state.state().turn_num;
+        self.match_ctx
+            .log(MatchLogMessage::TurnEvents { turn_num, events });
+    }
+
     fn log_player_action(&mut self, player_id: usize, player_action: PlayerAction) {
         match player_action {
             PlayerAction::Timeout => self.match_ctx.log(MatchLogMessage::Timeout {
@@ -179,6 +416,28 @@ impl PwMatch {
     }
 }
 
+fn command_feedback(player_action: &PlayerAction) -> proto::CommandFeedback {
+    match player_action {
+        PlayerAction::Commands(commands) => proto::CommandFeedback {
+            rejected_commands: commands
+                .iter()
+                .filter_map(|c| {
+                    c.error.clone().map(|error| proto::RejectedCommand {
+                        command: c.command.clone(),
+                        error,
+                    })
+                })
+                .collect(),
+            parse_error: None,
+        },
+        PlayerAction::ParseError { error, .. } => proto::CommandFeedback {
+            rejected_commands: Vec::new(),
+            parse_error: Some(error.to_string()),
+        },
+        PlayerAction::Timeout | PlayerAction::Terminated => proto::CommandFeedback::default(),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerCommand {
     #[serde(flatten)]
@@ -198,3 +457,83 @@ pub enum PlayerAction {
     },
     Commands(Vec<PlayerCommand>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_context::EventBus;
+    use crate::TimeBank;
+    use planetwars_rules::test_util::test_state;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::mpsc;
+
+    fn test_match(protocols: &[ProtocolVersion], time_control: TimeControl) -> PwMatch {
+        let state = test_state(&[("a", -3.0, Some(1)), ("b", 3.0, Some(2))]);
+        let match_state = PlanetWars::from_state(&state, protocols.len(), 100).unwrap();
+
+        let (logger, _log_rx) = mpsc::unbounded_channel();
+        let event_bus = Arc::new(Mutex::new(EventBus::new()));
+        let match_ctx = MatchCtx::new(event_bus, HashMap::new(), logger);
+        let players = protocols
+            .iter()
+            .map(|&protocol| PlayerSettings {
+                name: None,
+                protocol,
+                report_command_errors: false,
+            })
+            .collect();
+        PwMatch::create(match_ctx, match_state, players, time_control)
+    }
+
+    #[test]
+    fn test_time_bank() {
+        let time_control = TimeControl {
+            turn_timeout: Duration::from_millis(100),
+            first_turn_timeout: None,
+            time_bank: Some(TimeBank {
+                initial: Duration::from_millis(500),
+                increment: Duration::from_millis(50),
+            }),
+        };
+        let mut pw_match = test_match(&[ProtocolVersion::V1, ProtocolVersion::V1], time_control);
+        assert_eq!(pw_match.time_bank(1), Duration::from_millis(500));
+
+        pw_match.charge_time_bank(1, Duration::from_millis(200));
+        assert_eq!(pw_match.time_bank(1), Duration::from_millis(350));
+        // players that respond in time only get the increment
+        pw_match.charge_time_bank(1, Duration::ZERO);
+        assert_eq!(pw_match.time_bank(1), Duration::from_millis(400));
+        // the bank cannot go below zero
+        pw_match.charge_time_bank(1, Duration::from_secs(2));
+        assert_eq!(pw_match.time_bank(1), Duration::from_millis(50));
+
+        assert_eq!(pw_match.time_bank(2), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_without_time_bank() {
+        let mut pw_match = test_match(
+            &[ProtocolVersion::V1, ProtocolVersion::V1],
+            TimeControl::default(),
+        );
+        pw_match.charge_time_bank(1, Duration::from_millis(200));
+        assert_eq!(pw_match.time_bank(1), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_first_turn_timeout() {
+        let time_control = TimeControl {
+            turn_timeout: Duration::from_millis(100),
+            first_turn_timeout: Some(Duration::from_secs(2)),
+            time_bank: None,
+        };
+        let mut pw_match = test_match(&[ProtocolVersion::V1, ProtocolVersion::V2], time_control);
+        assert_eq!(pw_match.base_timeout(1), Duration::from_secs(2));
+        // bots that receive game info get the longer timeout for that instead
+        assert_eq!(pw_match.base_timeout(2), Duration::from_millis(100));
+
+        pw_match.first_turn = false;
+        assert_eq!(pw_match.base_timeout(1), Duration::from_millis(100));
+        assert_eq!(pw_match.base_timeout(2), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,219 @@
+use planetwars_rules::deserializer::count_players;
+use planetwars_rules::protocol::State;
+use planetwars_rules::{CombatRuleConfig, DeserializeError, PlanetWars, RulesVariant};
+
+use crate::match_log::MatchLogMessage;
+
+/// The first point where a replayed match no longer matches its log.
+#[derive(Debug)]
+pub struct Divergence {
+    pub turn_num: u64,
+    /// The state as it was recorded in the log
+    pub expected: State,
+    /// The state as it was recomputed by the rules engine
+    pub actual: State,
+}
+
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// Number of turns that were re-simulated
+    pub num_turns: u64,
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The log does not contain a gamestate to start from
+    NoInitialState,
+    InvalidState(DeserializeError),
+}
+
+/// Re-simulate a match from its log.
+///
+/// Starting from the first logged gamestate, using the logged rules variant and combat rule,
+/// the dispatches of every turn are
+/// executed again, and the resulting state is compared with the state that was
+/// logged for that turn. The replay stops at the first turn where they differ.
+pub fn replay_match<I>(log: I) -> Result<ReplayReport, ReplayError>
+where
+    I: IntoIterator<Item = MatchLogMessage>,
+{
+    let mut messages = log.into_iter();
+    // logs written before rules variants existed were played by the standard rules
+    let mut variant = RulesVariant::default();
+    let mut combat_rule = CombatRuleConfig::default();
+    let initial_state = messages
+        .find_map(|message| match message {
+            MatchLogMessage::GameState(state) => Some(state),
+            MatchLogMessage::RulesVariant(logged_variant) => {
+                variant = logged_variant;
+                None
+            }
+            MatchLogMessage::CombatRule { rule } => {
+                combat_rule = rule;
+                None
+            }
+            _ => None,
+        })
+        .ok_or(ReplayError::NoInitialState)?;
+
+    let num_players = count_players(&initial_state);
+    // a replay ends when the log does
+    let mut game = PlanetWars::from_state(&initial_state, num_players, u64::MAX)
+        .map_err(ReplayError::InvalidState)?;
+    game.set_rules_variant(variant);
+    game.set_combat_rule(combat_rule.build());
+
+    let mut num_turns = 0;
+    for message in messages {
+        match message {
+            MatchLogMessage::Dispatches {
+                player_id,
+                dispatches,
+            } => {
+                for dispatch in dispatches {
+                    // invalid commands were not executed in the original match either
+                    let _ = game.execute_command(player_id as usize, &dispatch.command);
+                }
+            }
+            MatchLogMessage::GameState(expected) => {
+                game.step();
+                num_turns += 1;
+
+                let actual = game.serialize_state();
+                if actual != expected {
+                    return Ok(ReplayReport {
+                        num_turns,
+                        divergence: Some(Divergence {
+                            turn_num: num_turns,
+                            expected,
+                            actual,
+                        }),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(ReplayReport {
+        num_turns,
+        divergence: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pw_match::PlayerCommand;
+    use planetwars_rules::protocol as proto;
+    use planetwars_rules::test_util::test_state;
+
+    fn initial_state() -> State {
+        test_state(&[("a", -3.0, Some(1)), ("b", 0.0, None), ("c", 3.0, Some(2))])
+    }
+
+    fn record_match(turns: &[Vec<(u32, proto::Command)>]) -> Vec<MatchLogMessage> {
+        record_match_with_rules(RulesVariant::default(), CombatRuleConfig::default(), turns)
+    }
+
+    fn record_match_with_rules(
+        variant: RulesVariant,
+        combat_rule: CombatRuleConfig,
+        turns: &[Vec<(u32, proto::Command)>],
+    ) -> Vec<MatchLogMessage> {
+        let initial = initial_state();
+        let mut game = PlanetWars::from_state(&initial, 2, 100).unwrap();
+        game.set_rules_variant(variant.clone());
+        game.set_combat_rule(combat_rule.build());
+        let mut log = vec![
+            MatchLogMessage::RulesVariant(variant),
+            MatchLogMessage::CombatRule { rule: combat_rule },
+            MatchLogMessage::GameState(initial),
+        ];
+        for turn in turns {
+            for (player_id, command) in turn {
+                let error = game.execute_command(*player_id as usize, command).err();
+                log.push(MatchLogMessage::Dispatches {
+                    player_id: *player_id,
+                    dispatches: vec![PlayerCommand {
+                        command: command.clone(),
+                        error,
+                    }],
+                });
+            }
+            game.step();
+            log.push(MatchLogMessage::GameState(game.serialize_state()));
+        }
+        log
+    }
+
+    fn command(origin: &str, destination: &str, ship_count: u64) -> proto::Command {
+        proto::Command {
+            origin: origin.to_string(),
+            destination: destination.to_string(),
+            ship_count,
+        }
+    }
+
+    #[test]
+    fn test_replay_matches_log() {
+        let log = record_match(&[
+            vec![(1, command("a", "b", 3)), (2, command("c", "b", 4))],
+            vec![(1, command("a", "c", 10))],
+            vec![],
+            vec![],
+        ]);
+
+        let report = replay_match(log).unwrap();
+        assert_eq!(report.num_turns, 4);
+        assert!(report.divergence.is_none());
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let mut log = record_match(&[vec![(1, command("a", "b", 3))], vec![], vec![]]);
+        if let MatchLogMessage::GameState(state) = &mut log[5] {
+            state.planets[0].ship_count += 1;
+        }
+
+        let report = replay_match(log).unwrap();
+        let divergence = report.divergence.expect("no divergence found");
+        assert_eq!(divergence.turn_num, 2);
+        assert_eq!(
+            divergence.actual.planets[0].ship_count + 1,
+            divergence.expected.planets[0].ship_count
+        );
+    }
+
+    #[test]
+    fn test_replay_uses_logged_variant() {
+        let variant = RulesVariant {
+            neutral_production: true,
+            ..Default::default()
+        };
+        let log = record_match_with_rules(variant.clone(), Default::default(), &[vec![], vec![]]);
+        let report = replay_match(log).unwrap();
+        assert!(report.divergence.is_none());
+
+        let mut log = record_match_with_rules(variant, Default::default(), &[vec![], vec![]]);
+        log.remove(0);
+        let report = replay_match(log).unwrap();
+        assert_eq!(report.divergence.unwrap().turn_num, 1);
+    }
+
+    #[test]
+    fn test_replay_uses_logged_combat_rule() {
+        let combat_rule = CombatRuleConfig::DefenderBonus { bonus_percent: 100 };
+        // the attack on neutral planet b arrives on turn 3
+        let turns = [vec![(1, command("a", "b", 3))], vec![], vec![], vec![]];
+        let log = record_match_with_rules(Default::default(), combat_rule.clone(), &turns);
+        let report = replay_match(log).unwrap();
+        assert!(report.divergence.is_none());
+
+        let mut log = record_match_with_rules(Default::default(), combat_rule, &turns);
+        log.remove(1);
+        let report = replay_match(log).unwrap();
+        assert_eq!(report.divergence.unwrap().turn_num, 3);
+    }
+}
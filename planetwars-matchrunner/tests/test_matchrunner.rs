@@ -7,10 +7,15 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc;
 
+use planetwars_matchrunner::bot_runner::Bot;
 use planetwars_matchrunner::docker_runner::DockerBotSpec;
+use planetwars_matchrunner::legacy_protocol::LegacyProtocolBotSpec;
 use planetwars_matchrunner::match_context::{EventBus, MatchCtx, RequestError};
-use planetwars_matchrunner::BotSpec;
-use planetwars_matchrunner::{run_match, MatchConfig, MatchPlayer};
+use planetwars_matchrunner::match_log::MatchLogMessage;
+use planetwars_matchrunner::{match_log, BotSpec, BotStartError};
+use planetwars_matchrunner::{run_match, MatchConfig, MatchPlayer, TimeControl, DEFAULT_MAX_TURNS};
+use planetwars_rules::protocol::{self as proto, ProtocolVersion};
+use planetwars_rules::{CombatRuleConfig, FogOfWar, MapSource, WinReason};
 
 const PYTHON_IMAGE: &str = "python:3.10-slim-buster";
 
@@ -28,27 +33,50 @@ fn simple_python_docker_bot_spec(source_dir: &str, file_name: &str) -> DockerBot
     }
 }
 
+fn simple_python_local_bot(source_dir: &str, file_name: &str) -> Bot {
+    Bot {
+        working_dir: PathBuf::from(source_dir),
+        argv: vec!["python3".to_string(), file_name.to_string()],
+    }
+}
+
+/// A match on the abc map between the given bots, which speak protocol V1
+fn match_config(
+    log_file: &tempfile::NamedTempFile,
+    bot_specs: Vec<Box<dyn BotSpec>>,
+) -> MatchConfig {
+    MatchConfig {
+        map_name: "abc".to_string(),
+        map: MapSource::File(PathBuf::from("maps/abc.json")),
+        log_path: PathBuf::from(log_file.path()),
+        players: bot_specs
+            .into_iter()
+            .map(|bot_spec| MatchPlayer {
+                bot_spec,
+                name: None,
+                protocol: ProtocolVersion::V1,
+                report_command_errors: false,
+            })
+            .collect(),
+        max_turns: DEFAULT_MAX_TURNS,
+        time_control: TimeControl::default(),
+        tiebreaks: Vec::new(),
+        rules_variant: None,
+        combat_rule: CombatRuleConfig::default(),
+        fog_of_war: None,
+        teams: Vec::new(),
+    }
+}
+
 #[tokio::test]
 async fn match_does_run() {
     let bot = simple_python_docker_bot_spec("./bots/simplebot", "simplebot.py");
 
     let log_file = tempfile::NamedTempFile::new().unwrap();
 
-    let config = MatchConfig {
-        map_name: "abc".to_string(),
-        map_path: PathBuf::from("maps/abc.json"),
-        log_path: PathBuf::from(log_file.path()),
-        players: vec![
-            MatchPlayer {
-                bot_spec: Box::new(bot.clone()),
-            },
-            MatchPlayer {
-                bot_spec: Box::new(bot.clone()),
-            },
-        ],
-    };
+    let config = match_config(&log_file, vec![Box::new(bot.clone()), Box::new(bot)]);
 
-    run_match(config).await;
+    run_match(config).await.unwrap();
 
     let line_count = std::io::BufReader::new(log_file.as_file()).lines().count();
     assert!(line_count > 0);
@@ -58,24 +86,18 @@ async fn match_does_run() {
 async fn player_results() {
     let log_file = tempfile::NamedTempFile::new().unwrap();
 
-    let config = MatchConfig {
-        map_name: "abc".to_string(),
-        map_path: PathBuf::from("maps/abc.json"),
-        log_path: PathBuf::from(log_file.path()),
-        players: vec![
-            MatchPlayer {
-                bot_spec: Box::new(simple_python_docker_bot_spec(
-                    "./bots/simplebot",
-                    "simplebot.py",
-                )),
-            },
-            MatchPlayer {
-                bot_spec: Box::new(simple_python_docker_bot_spec("./bots", "crash_bot.py")),
-            },
+    let config = match_config(
+        &log_file,
+        vec![
+            Box::new(simple_python_docker_bot_spec(
+                "./bots/simplebot",
+                "simplebot.py",
+            )),
+            Box::new(simple_python_docker_bot_spec("./bots", "crash_bot.py")),
         ],
-    };
+    );
 
-    let outcome = run_match(config).await;
+    let outcome = run_match(config).await.unwrap();
     assert_eq!(outcome.player_outcomes.len(), 2);
     assert!(!outcome.player_outcomes[0].crashed);
     assert!(!outcome.player_outcomes[0].had_errors);
@@ -83,22 +105,32 @@ async fn player_results() {
     assert!(!outcome.player_outcomes[1].had_errors);
 }
 
-/// creates a simple match ctx which only holds a single bot
-async fn with_bot_match_ctx<B, F>(bot_spec: B, func: F)
+/// creates a simple match ctx which only holds a single bot.
+/// Returns the messages that were logged.
+async fn with_bot_match_ctx<B, F>(bot_spec: B, func: F) -> Vec<MatchLogMessage>
 where
     F: FnOnce(&mut MatchCtx) -> Pin<Box<dyn '_ + Future<Output = ()>>>,
     B: BotSpec,
 {
     let event_bus = Arc::new(Mutex::new(EventBus::new()));
-    let (logger, _rx) = mpsc::unbounded_channel();
+    let (logger, mut log_rx) = mpsc::unbounded_channel();
 
-    let player_handle = bot_spec.run_bot(1, event_bus.clone(), logger.clone()).await;
+    let player_handle = bot_spec
+        .run_bot(1, event_bus.clone(), logger.clone())
+        .await
+        .unwrap();
     let mut players = HashMap::new();
     players.insert(1, player_handle);
     let mut ctx = MatchCtx::new(event_bus, players, logger);
 
     func(&mut ctx).await;
     ctx.shutdown().await;
+
+    let mut log = Vec::new();
+    while let Some(message) = log_rx.recv().await {
+        log.push(message);
+    }
+    log
 }
 
 #[tokio::test]
@@ -165,3 +197,245 @@ async fn test_long_line() {
     })
     .await;
 }
+
+#[tokio::test]
+async fn local_match_does_run() {
+    let bot = simple_python_local_bot("./bots/simplebot", "simplebot.py");
+    let log_file = tempfile::NamedTempFile::new().unwrap();
+
+    let config = match_config(&log_file, vec![Box::new(bot.clone()), Box::new(bot)]);
+
+    let outcome = run_match(config).await.unwrap();
+    assert!(outcome.player_outcomes.iter().all(|p| !p.crashed));
+}
+
+#[tokio::test]
+async fn local_match_combat_rule() {
+    let bot = simple_python_local_bot("./bots/simplebot", "simplebot.py");
+    let log_file = tempfile::NamedTempFile::new().unwrap();
+
+    let mut config = match_config(&log_file, vec![Box::new(bot.clone()), Box::new(bot)]);
+    config.max_turns = 1;
+    config.combat_rule = CombatRuleConfig::ProportionalAttrition;
+    run_match(config).await.unwrap();
+
+    let log = match_log::read_log(std::io::BufReader::new(log_file.as_file())).unwrap();
+    assert!(log.iter().any(|message| matches!(
+        message,
+        MatchLogMessage::CombatRule {
+            rule: CombatRuleConfig::ProportionalAttrition
+        }
+    )));
+}
+
+#[tokio::test]
+async fn local_match_fog_of_war() {
+    let bot = simple_python_local_bot("./bots/simplebot", "simplebot.py");
+    let log_file = tempfile::NamedTempFile::new().unwrap();
+
+    let mut config = match_config(&log_file, vec![Box::new(bot.clone()), Box::new(bot)]);
+    config.max_turns = 1;
+    config.fog_of_war = Some(FogOfWar { vision_radius: 5.0 });
+    run_match(config).await.unwrap();
+
+    let log = match_log::read_log(std::io::BufReader::new(log_file.as_file())).unwrap();
+    assert!(log.iter().any(|message| matches!(
+        message,
+        MatchLogMessage::FogOfWar(FogOfWar { vision_radius }) if *vision_radius == 5.0
+    )));
+}
+
+#[tokio::test]
+async fn local_match_forfeit() {
+    let log_file = tempfile::NamedTempFile::new().unwrap();
+    let missing_bot = Bot {
+        working_dir: PathBuf::from("./bots"),
+        argv: vec!["./does_not_exist".to_string()],
+    };
+
+    let config = match_config(
+        &log_file,
+        vec![
+            Box::new(simple_python_local_bot("./bots/simplebot", "simplebot.py")),
+            Box::new(missing_bot),
+        ],
+    );
+
+    let outcome = run_match(config).await.unwrap();
+    // the match ends before the first turn
+    assert_eq!(outcome.num_turns, 0);
+    assert_eq!(outcome.winner, Some(1));
+    assert_eq!(outcome.win_reason, Some(WinReason::Elimination));
+    assert_eq!(outcome.player_outcomes[0].forfeit, None);
+    assert!(matches!(
+        outcome.player_outcomes[1].forfeit,
+        Some(BotStartError::SpawnFailed { .. })
+    ));
+
+    let log = match_log::read_log(std::io::BufReader::new(log_file.as_file())).unwrap();
+    assert!(matches!(
+        log.first(),
+        Some(MatchLogMessage::Forfeit { player_id: 2, .. })
+    ));
+}
+
+#[tokio::test]
+async fn local_runner_success() {
+    let bot_spec = simple_python_local_bot("./bots", "echo_bot.py");
+    with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let resp = ctx
+                .request(1, b"sup".to_vec(), Duration::from_millis(1000))
+                .await;
+
+            assert_eq!(resp, Ok(b"sup".to_vec()));
+        }
+        .boxed()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn local_runner_timeout() {
+    let bot_spec = simple_python_local_bot("./bots", "timeout_bot.py");
+    with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let resp = ctx
+                .request(1, b"sup".to_vec(), Duration::from_millis(200))
+                .await;
+
+            assert_eq!(resp, Err(RequestError::Timeout));
+        }
+        .boxed()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn local_runner_late_response() {
+    let bot_spec = simple_python_local_bot("./bots", "late_bot.py");
+    let log = with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let resp = ctx
+                .request(1, b"first".to_vec(), Duration::from_millis(100))
+                .await;
+            assert_eq!(resp, Err(RequestError::Timeout));
+
+            // the late response arrives after the next request was sent,
+            // but it answers the request that timed out
+            let resp = ctx
+                .request(1, b"second".to_vec(), Duration::from_millis(1000))
+                .await;
+            assert_eq!(resp, Ok(b"second".to_vec()));
+        }
+        .boxed()
+    })
+    .await;
+
+    let late_responses: Vec<_> = log
+        .into_iter()
+        .filter_map(|message| match message {
+            MatchLogMessage::LateResponse {
+                player_id,
+                response,
+            } => {
+                assert_eq!(player_id, 1);
+                Some(response)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(late_responses, vec!["first".to_string()]);
+}
+
+#[tokio::test]
+async fn local_runner_echoed_request_id() {
+    let bot_spec = simple_python_local_bot("./bots", "late_bot.py");
+    with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let resp = ctx
+                .request(
+                    1,
+                    br#"{"request_id":0}"#.to_vec(),
+                    Duration::from_millis(100),
+                )
+                .await;
+            assert_eq!(resp, Err(RequestError::Timeout));
+
+            // the late response arrives after the next request was sent,
+            // but the echoed id shows which request it belongs to
+            let resp = ctx
+                .request(
+                    1,
+                    br#"{"request_id":1}"#.to_vec(),
+                    Duration::from_millis(1000),
+                )
+                .await;
+            assert_eq!(resp, Ok(br#"{"request_id":1}"#.to_vec()));
+        }
+        .boxed()
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn local_legacy_bot_late_turn() {
+    let bot_spec = LegacyProtocolBotSpec {
+        bot_spec: Box::new(simple_python_local_bot("./bots", "slow_legacy_bot.py")),
+    };
+    let log = with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let state = br#"{"planets": [], "expeditions": []}"#;
+            let resp = ctx
+                .request(1, state.to_vec(), Duration::from_millis(100))
+                .await;
+            assert_eq!(resp, Err(RequestError::Timeout));
+
+            // all orders of the first turn arrive late, up to its go
+            let resp = ctx
+                .request(1, state.to_vec(), Duration::from_millis(1000))
+                .await
+                .unwrap();
+            let action: proto::Action = serde_json::from_slice(&resp).unwrap();
+            let ship_counts: Vec<_> = action.commands.iter().map(|c| c.ship_count).collect();
+            assert_eq!(ship_counts, vec![2, 2]);
+        }
+        .boxed()
+    })
+    .await;
+
+    let late_responses: Vec<_> = log
+        .into_iter()
+        .filter_map(|message| match message {
+            MatchLogMessage::LateResponse { response, .. } => Some(response),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(late_responses, vec!["0 1 1", "0 1 1"]);
+}
+
+#[tokio::test]
+async fn local_runner_crash() {
+    let bot_spec = simple_python_local_bot("./bots", "crash_bot.py");
+    let log = with_bot_match_ctx(bot_spec, |ctx| {
+        async move {
+            let resp = ctx
+                .request(1, b"sup".to_vec(), Duration::from_millis(1000))
+                .await;
+            assert_eq!(resp, Err(RequestError::BotTerminated));
+        }
+        .boxed()
+    })
+    .await;
+
+    let mut stderr = Vec::new();
+    for message in log {
+        if let MatchLogMessage::StdErr(message) = message {
+            assert_eq!(message.player_id, 1);
+            stderr.push(message.message);
+        }
+    }
+    assert!(stderr
+        .iter()
+        .any(|line| line.contains("This bot does not run!")));
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::config::{Map, MAP_FORMAT_VERSION};
+use super::protocol as proto;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassicMapError {
+    /// The line with this (1-based) number could not be parsed
+    InvalidLine(usize),
+    /// The line with this number describes a fleet, which a map cannot contain
+    UnsupportedFleet(usize),
+}
+
+impl fmt::Display for ClassicMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassicMapError::InvalidLine(line_num) => write!(f, "invalid line {}", line_num),
+            ClassicMapError::UnsupportedFleet(line_num) => {
+                write!(f, "line {}: maps cannot contain fleets", line_num)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClassicMapError {}
+
+/// Name of the planet with the given number in the classic format
+pub fn classic_planet_name(planet_num: usize) -> String {
+    format!("p{}", planet_num)
+}
+
+/// Convert a map in the text format of the original Google AI Challenge.
+///
+/// Every planet is described by a line `P x y owner ships growth`,
+/// where owner 0 means the planet is neutral. Planets are identified by
+/// their position in the file, starting at 0, and named accordingly
+/// (see `classic_planet_name`). Everything after a `#` is a comment.
+pub fn parse_classic_map(text: &str) -> Result<Map, ClassicMapError> {
+    let mut planets = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_num = line_idx + 1;
+        let content = line.split('#').next().unwrap_or("");
+        let mut fields = content.split_whitespace();
+        match fields.next() {
+            None => continue,
+            Some("P") => (),
+            Some("F") => return Err(ClassicMapError::UnsupportedFleet(line_num)),
+            Some(_) => return Err(ClassicMapError::InvalidLine(line_num)),
+        }
+        let fields: Vec<&str> = fields.collect();
+        let planet =
+            parse_planet(planets.len(), &fields).ok_or(ClassicMapError::InvalidLine(line_num))?;
+        planets.push(planet);
+    }
+
+    let num_players = planets.iter().filter_map(|p| p.owner).max();
+    Ok(Map {
+        version: MAP_FORMAT_VERSION,
+        author: None,
+        description: None,
+        num_players,
+        rules: None,
+        planets,
+    })
+}
+
+/// Write a game state in the classic format, as sent to bots of the original
+/// AI Challenge. Planets are numbered in the order of the state.
+/// Every line, including the last, is terminated by a newline.
+pub fn format_classic_state(state: &proto::State) -> String {
+    let planet_nums: HashMap<&str, usize> = state
+        .planets
+        .iter()
+        .enumerate()
+        .map(|(num, planet)| (planet.name.as_str(), num))
+        .collect();
+
+    let mut text = String::new();
+    for planet in state.planets.iter() {
+        text.push_str(&format!(
+            "P {} {} {} {} {}\n",
+            planet.x,
+            planet.y,
+            planet.owner.unwrap_or(0),
+            planet.ship_count,
+            planet.growth_rate
+        ));
+    }
+    for expedition in state.expeditions.iter() {
+        let origin = planet_nums[expedition.origin.as_str()];
+        let destination = planet_nums[expedition.destination.as_str()];
+        // the total trip length is not part of the state, so we recompute it
+        let trip_length = classic_distance(&state.planets[origin], &state.planets[destination])
+            .max(expedition.turns_remaining);
+        text.push_str(&format!(
+            "F {} {} {} {} {} {}\n",
+            expedition.owner,
+            expedition.ship_count,
+            origin,
+            destination,
+            trip_length,
+            expedition.turns_remaining
+        ));
+    }
+    text
+}
+
+/// Parse an order in the classic `origin destination ships` format,
+/// where planets are referred to by their number in the given state.
+pub fn parse_classic_order(line: &str, state: &proto::State) -> Option<proto::Command> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 3 {
+        return None;
+    }
+    let planet_name = |field: &str| -> Option<String> {
+        let planet_num: usize = field.parse().ok()?;
+        // unknown planets are left for the rules to reject
+        Some(match state.planets.get(planet_num) {
+            Some(planet) => planet.name.clone(),
+            None => classic_planet_name(planet_num),
+        })
+    };
+    Some(proto::Command {
+        origin: planet_name(fields[0])?,
+        destination: planet_name(fields[1])?,
+        ship_count: fields[2].parse().ok()?,
+    })
+}
+
+fn classic_distance(a: &proto::Planet, b: &proto::Planet) -> u64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt().ceil() as u64
+}
+
+fn parse_planet(planet_num: usize, fields: &[&str]) -> Option<proto::Planet> {
+    if fields.len() != 5 {
+        return None;
+    }
+    let owner: usize = fields[2].parse().ok()?;
+    Some(proto::Planet {
+        name: classic_planet_name(planet_num),
+        x: fields[0].parse().ok()?,
+        y: fields[1].parse().ok()?,
+        owner: if owner == 0 { None } else { Some(owner) },
+        ship_count: fields[3].parse().ok()?,
+        growth_rate: fields[4].parse().ok()?,
+        hidden: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_classic_map() {
+        let map = parse_classic_map(
+            "# a small map\n\
+             P 11.5 11.5 0 100 5\n\
+             P 3 4 1 100 5 # home\n\
+             \n\
+             P 20 19 2 100 5\n",
+        )
+        .unwrap();
+        assert_eq!(map.num_players, Some(2));
+        assert_eq!(map.planets.len(), 3);
+        assert_eq!(map.planets[0].name, "p0");
+        assert_eq!(map.planets[0].owner, None);
+        assert_eq!(map.planets[1].owner, Some(1));
+        assert_eq!(map.planets[1].x, 3.0);
+        assert_eq!(map.planets[2].growth_rate, 5);
+        assert!(map.validate(2).is_ok());
+    }
+
+    #[test]
+    fn test_parse_invalid_classic_map() {
+        assert_eq!(
+            parse_classic_map("P 1 2 0 10 1\nP 1 2 x 10 1\n").err(),
+            Some(ClassicMapError::InvalidLine(2))
+        );
+        assert_eq!(
+            parse_classic_map("P 1 2 1 10\n").err(),
+            Some(ClassicMapError::InvalidLine(1))
+        );
+        assert_eq!(
+            parse_classic_map("P 1 2 1 10 1\nF 1 5 0 1 3 2\n").err(),
+            Some(ClassicMapError::UnsupportedFleet(2))
+        );
+    }
+
+    #[test]
+    fn test_format_classic_state() {
+        let mut map = parse_classic_map("P 0 0 1 10 5\nP 3 4 0 2 1\n").unwrap();
+        map.planets[1].name = "b".to_string();
+        let state = proto::State {
+            planets: map.planets,
+            expeditions: vec![proto::Expedition {
+                id: 0,
+                ship_count: 4,
+                origin: "p0".to_string(),
+                destination: "b".to_string(),
+                owner: 1,
+                turns_remaining: 3,
+            }],
+            teams: Vec::new(),
+        };
+        assert_eq!(
+            format_classic_state(&state),
+            "P 0 0 1 10 5\nP 3 4 0 2 1\nF 1 4 0 1 5 3\n"
+        );
+
+        let command = parse_classic_order("0 1 3", &state).unwrap();
+        assert_eq!(command.origin, "p0");
+        assert_eq!(command.destination, "b");
+        assert_eq!(command.ship_count, 3);
+        assert_eq!(parse_classic_order("7 1 3", &state).unwrap().origin, "p7");
+        assert!(parse_classic_order("0 1", &state).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::rules::Fleet;
+
+/// Decides the outcome of a fight between the fleets present at a planet.
+pub trait CombatRule: Debug + Send + Sync {
+    /// Resolve combat between the given fleets, which all have a different owner.
+    /// `defender` is the index of the fleet that was stationed at the planet
+    /// at the start of the turn, if there was one.
+    /// Returns the surviving fleet, if any fleet survived.
+    fn resolve(&self, fleets: Vec<Fleet>, defender: Option<usize>) -> Option<Fleet>;
+
+    /// The config this rule can be rebuilt from.
+    /// Custom rules have none, so states using them cannot be serialized.
+    fn config(&self) -> Option<CombatRuleConfig> {
+        None
+    }
+}
+
+/// Selects one of the built-in combat rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CombatRuleConfig {
+    #[default]
+    LargestFleetWins,
+    ProportionalAttrition,
+    DefenderBonus {
+        bonus_percent: u64,
+    },
+}
+
+impl CombatRuleConfig {
+    pub fn build(&self) -> Arc<dyn CombatRule> {
+        match *self {
+            CombatRuleConfig::LargestFleetWins => Arc::new(LargestFleetWins),
+            CombatRuleConfig::ProportionalAttrition => Arc::new(ProportionalAttrition),
+            CombatRuleConfig::DefenderBonus { bonus_percent } => {
+                Arc::new(DefenderBonus { bonus_percent })
+            }
+        }
+    }
+}
+
+/// The standard rule: the largest fleet wins, and loses as many ships as
+/// the second largest fleet had.
+#[derive(Debug, Clone, Copy)]
+pub struct LargestFleetWins;
+
+impl CombatRule for LargestFleetWins {
+    fn resolve(&self, mut fleets: Vec<Fleet>, _defender: Option<usize>) -> Option<Fleet> {
+        // The player owning the largest fleet present will win the combat.
+        // Here, we resolve how many ships he will have left.
+        fleets.sort_by(|a, b| a.ship_count.cmp(&b.ship_count).reverse());
+        while fleets.len() > 1 {
+            let fleet = fleets.pop().unwrap();
+            // destroy some ships
+            for other in fleets.iter_mut() {
+                other.ship_count -= fleet.ship_count;
+            }
+
+            // remove dead fleets
+            while fleets.last().map(|f| f.ship_count) == Some(0) {
+                fleets.pop();
+            }
+        }
+        fleets.pop()
+    }
+
+    fn config(&self) -> Option<CombatRuleConfig> {
+        Some(CombatRuleConfig::LargestFleetWins)
+    }
+}
+
+/// Lanchester's square law: every fleet loses ships in proportion to the size
+/// of the forces it is fighting. The largest fleet wins, keeping
+/// `sqrt(largest² - sum(other²))` ships.
+#[derive(Debug, Clone, Copy)]
+pub struct ProportionalAttrition;
+
+impl CombatRule for ProportionalAttrition {
+    fn resolve(&self, fleets: Vec<Fleet>, _defender: Option<usize>) -> Option<Fleet> {
+        let (winner_idx, winner) = fleets
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, fleet)| fleet.ship_count)?;
+
+        let winner_strength = (winner.ship_count as u128).pow(2);
+        let enemy_strength: u128 = fleets
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| idx != winner_idx)
+            .map(|(_, fleet)| (fleet.ship_count as u128).pow(2))
+            .sum();
+
+        if winner_strength <= enemy_strength {
+            return None;
+        }
+
+        Some(Fleet {
+            owner: winner.owner,
+            ship_count: integer_sqrt(winner_strength - enemy_strength) as u64,
+        })
+    }
+
+    fn config(&self) -> Option<CombatRuleConfig> {
+        Some(CombatRuleConfig::ProportionalAttrition)
+    }
+}
+
+/// Like `LargestFleetWins`, but the ships that were defending the planet
+/// fight with a bonus of the given percentage.
+#[derive(Debug, Clone, Copy)]
+pub struct DefenderBonus {
+    pub bonus_percent: u64,
+}
+
+impl DefenderBonus {
+    /// Strength of a single ship of the given fleet, in hundredths of a ship.
+    fn ship_strength(&self, fleet_idx: usize, defender: Option<usize>) -> u64 {
+        if defender == Some(fleet_idx) {
+            100 + self.bonus_percent
+        } else {
+            100
+        }
+    }
+}
+
+impl CombatRule for DefenderBonus {
+    fn resolve(&self, fleets: Vec<Fleet>, defender: Option<usize>) -> Option<Fleet> {
+        let mut strengths = fleets
+            .iter()
+            .enumerate()
+            .map(|(idx, fleet)| (fleet.ship_count * self.ship_strength(idx, defender), idx))
+            .collect::<Vec<_>>();
+        strengths.sort_unstable_by(|a, b| b.cmp(a));
+
+        let (winner_strength, winner_idx) = strengths[0];
+        let runner_up_strength = strengths.get(1).map_or(0, |&(strength, _)| strength);
+        let ship_count =
+            (winner_strength - runner_up_strength) / self.ship_strength(winner_idx, defender);
+
+        if ship_count == 0 {
+            return None;
+        }
+
+        Some(Fleet {
+            owner: fleets[winner_idx].owner,
+            ship_count,
+        })
+    }
+
+    fn config(&self) -> Option<CombatRuleConfig> {
+        Some(CombatRuleConfig::DefenderBonus {
+            bonus_percent: self.bonus_percent,
+        })
+    }
+}
+
+fn integer_sqrt(n: u128) -> u128 {
+    let mut root = (n as f64).sqrt() as u128;
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fleets(ship_counts: &[u64]) -> Vec<Fleet> {
+        ship_counts
+            .iter()
+            .enumerate()
+            .map(|(owner, &ship_count)| Fleet {
+                owner: Some(owner),
+                ship_count,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_proportional_attrition() {
+        let survivor = ProportionalAttrition.resolve(fleets(&[3, 5]), None);
+        assert_eq!(
+            survivor,
+            Some(Fleet {
+                owner: Some(1),
+                ship_count: 4
+            })
+        );
+
+        let survivor = ProportionalAttrition.resolve(fleets(&[10, 9, 9]), None);
+        assert_eq!(survivor, None);
+    }
+
+    #[test]
+    fn test_defender_bonus() {
+        let rule = DefenderBonus { bonus_percent: 50 };
+
+        let survivor = rule.resolve(fleets(&[10, 12]), Some(0));
+        assert_eq!(
+            survivor,
+            Some(Fleet {
+                owner: Some(0),
+                ship_count: 2
+            })
+        );
+
+        let survivor = rule.resolve(fleets(&[10, 20]), Some(0));
+        assert_eq!(
+            survivor,
+            Some(Fleet {
+                owner: Some(1),
+                ship_count: 5
+            })
+        );
+
+        // without a defender, this is the standard rule
+        let survivor = rule.resolve(fleets(&[10, 12]), None);
+        assert_eq!(survivor, LargestFleetWins.resolve(fleets(&[10, 12]), None));
+    }
+}
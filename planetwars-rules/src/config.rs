@@ -1,83 +1,535 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde_json;
 
+use super::combat::CombatRuleConfig;
+use super::deserializer::{deserialize, DeserializeError};
+use super::outcome::Tiebreak;
 use super::protocol as proto;
 use super::rules::*;
+use super::scenario::Scenario;
+use super::variant::{RulesVariant, RulesVariantError};
+use super::visibility::FogOfWar;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub map_file: PathBuf,
+    #[serde(flatten)]
+    pub map: MapSource,
     pub max_turns: u64,
+    #[serde(default)]
+    pub combat_rule: CombatRuleConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fog_of_war: Option<FogOfWar>,
+    /// Decide the winner when the turn limit is reached, applied in order.
+    /// Without tiebreaks, such a game is a draw.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiebreaks: Vec<Tiebreak>,
+    /// Overrides the rules variant of the map
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<RulesVariant>,
+    /// Players that play together, as lists of player numbers.
+    /// Teams are numbered in this order, starting at 1.
+    /// Overrides the teams of a scenario.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<Vec<usize>>,
+}
+
+/// Where the map for a game comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MapSource {
+    /// Read the map from a file when the game is created
+    #[serde(rename = "map_file")]
+    File(PathBuf),
+    /// An already loaded map
+    #[serde(rename = "map")]
+    Inline(Map),
+    /// Start from a game in progress, read from a file when the game is created
+    #[serde(rename = "scenario_file")]
+    ScenarioFile(PathBuf),
+    /// Start from a game in progress
+    #[serde(rename = "scenario")]
+    Scenario(Scenario),
 }
 
 impl Config {
-    pub fn create_state(&self, num_players: usize) -> PwState {
-        let planets = self.load_map(num_players);
+    pub fn create_state(&self, num_players: usize) -> Result<PwState, MapError> {
+        let mut state = match &self.map {
+            MapSource::File(path) => self.create_map_state(&Map::load(path)?, num_players),
+            MapSource::Inline(map) => self.create_map_state(map, num_players),
+            MapSource::ScenarioFile(path) => {
+                self.create_scenario_state(&Scenario::load(path)?, num_players)
+            }
+            MapSource::Scenario(scenario) => self.create_scenario_state(scenario, num_players),
+        }?;
+        if !self.teams.is_empty() {
+            assign_teams(&mut state.players, &self.teams)?;
+        }
+        Ok(state)
+    }
+
+    fn create_scenario_state(
+        &self,
+        scenario: &Scenario,
+        num_players: usize,
+    ) -> Result<PwState, MapError> {
+        if scenario.num_players != num_players {
+            return Err(MapError::PlayerCountMismatch {
+                expected: scenario.num_players,
+                actual: num_players,
+            });
+        }
+        let variant = self
+            .variant
+            .clone()
+            .or_else(|| scenario.rules.clone())
+            .unwrap_or_default();
+        variant.validate()?;
+        check_unique_planet_names(scenario.state.planets.iter().map(|p| p.name.as_str()))?;
+
+        let mut state = deserialize(&scenario.state, num_players, self.max_turns)
+            .map_err(MapError::InvalidScenario)?;
+        state.turn_num = scenario.turn_num;
+        state.combat_rule = self.combat_rule.build();
+        state.variant = variant;
+        Ok(state)
+    }
+
+    fn create_map_state(&self, map: &Map, num_players: usize) -> Result<PwState, MapError> {
+        map.validate(num_players)?;
+
+        let variant = self
+            .variant
+            .clone()
+            .or_else(|| map.rules.clone())
+            .unwrap_or_default();
+        variant.validate()?;
+
         let players = (0..num_players)
             .map(|player_num| Player {
                 id: player_num + 1,
                 alive: true,
+                team: None,
             })
             .collect();
 
-        PwState {
+        Ok(PwState {
             players,
-            planets,
+            planets: create_planets(map),
             expeditions: Vec::new(),
             expedition_num: 0,
             turn_num: 0,
             max_turns: self.max_turns,
+            combat_rule: self.combat_rule.build(),
+            variant,
+        })
+    }
+}
+
+fn assign_teams(players: &mut [Player], teams: &[Vec<usize>]) -> Result<(), MapError> {
+    for player in players.iter_mut() {
+        player.team = None;
+    }
+    for (team_idx, team) in teams.iter().enumerate() {
+        for &player_num in team {
+            match player_num
+                .checked_sub(1)
+                .and_then(|idx| players.get_mut(idx))
+            {
+                Some(player) if player.team.is_none() => player.team = Some(team_idx + 1),
+                _ => return Err(MapError::InvalidTeamMember(player_num)),
+            }
         }
     }
+    Ok(())
+}
 
-    fn load_map(&self, num_players: usize) -> Vec<Planet> {
-        let map = self.read_map().expect("[PLANET_WARS] reading map failed");
-
-        map.planets
-            .into_iter()
-            .enumerate()
-            .map(|(num, planet)| {
-                let mut fleets = Vec::new();
-                let owner = planet.owner.and_then(|owner_num| {
-                    // in the current map format, player numbers start at 1.
-                    // TODO: we might want to change this.
-                    // ignore players that are not in the game
-                    if owner_num > 0 && owner_num <= num_players {
-                        Some(owner_num - 1)
-                    } else {
-                        None
-                    }
+fn create_planets(map: &Map) -> Vec<Planet> {
+    map.planets
+        .iter()
+        .enumerate()
+        .map(|(num, planet)| {
+            let mut fleets = Vec::new();
+            // in the current map format, player numbers start at 1.
+            // TODO: we might want to change this.
+            let owner = planet.owner.map(|owner_num| owner_num - 1);
+            if planet.ship_count > 0 {
+                fleets.push(Fleet {
+                    owner,
+                    ship_count: planet.ship_count,
                 });
-                if planet.ship_count > 0 {
-                    fleets.push(Fleet {
+            }
+            Planet {
+                id: num,
+                name: planet.name.clone(),
+                x: planet.x,
+                y: planet.y,
+                fleets,
+                growth_rate: planet.growth_rate,
+            }
+        })
+        .collect()
+}
+
+/// The most recent version of the map format.
+///
+/// Version 1 maps do not carry a version field and only list planets,
+/// every planet producing one ship per turn.
+/// Version 2 adds per-planet growth rates and optional map metadata.
+/// Version 3 adds an optional rules variant.
+pub const MAP_FORMAT_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Map {
+    #[serde(default = "legacy_map_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The amount of players this map was designed for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_players: Option<usize>,
+    /// The rules this map is played by, when they differ from the standard rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<RulesVariant>,
+    pub planets: Vec<proto::Planet>,
+}
+
+fn legacy_map_version() -> u32 {
+    1
+}
+
+impl Map {
+    /// Read a map from a file. The map is not validated.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Map, MapError> {
+        let mut file = File::open(path)?;
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+        let map = serde_json::from_str(&buf)?;
+        Ok(map)
+    }
+
+    /// Check whether a game for the given amount of players can be played on this map.
+    pub fn validate(&self, num_players: usize) -> Result<(), MapError> {
+        if self.version > MAP_FORMAT_VERSION {
+            return Err(MapError::UnsupportedVersion(self.version));
+        }
+        if self.planets.is_empty() {
+            return Err(MapError::NoPlanets);
+        }
+        if let Some(rules) = &self.rules {
+            rules.validate()?;
+        }
+
+        check_unique_planet_names(self.planets.iter().map(|p| p.name.as_str()))?;
+        for planet in self.planets.iter() {
+            match planet.owner {
+                Some(owner) if owner == 0 || owner > num_players => {
+                    return Err(MapError::UnknownOwner {
+                        planet: planet.name.clone(),
                         owner,
-                        ship_count: planet.ship_count,
                     });
                 }
-                Planet {
-                    id: num,
-                    name: planet.name,
-                    x: planet.x,
-                    y: planet.y,
-                    fleets,
-                }
-            })
-            .collect()
+                _ => (),
+            }
+        }
+        Ok(())
     }
+}
 
-    fn read_map(&self) -> io::Result<Map> {
-        let mut file = File::open(&self.map_file)?;
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        Ok(serde_json::from_str(&buf)?)
+/// Planets are referred to by name, so their names should be unique.
+fn check_unique_planet_names<'a>(names: impl Iterator<Item = &'a str>) -> Result<(), MapError> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(MapError::DuplicatePlanetName(name.to_string()));
+        }
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Map {
-    pub planets: Vec<proto::Planet>,
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    NoPlanets,
+    DuplicatePlanetName(String),
+    /// A planet is owned by a player that is not in the game
+    UnknownOwner {
+        planet: String,
+        owner: usize,
+    },
+    InvalidRules(RulesVariantError),
+    /// The state of a scenario is inconsistent
+    InvalidScenario(DeserializeError),
+    /// A scenario is played by a different amount of players than it was made for
+    PlayerCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// A team member is not in the game, or is in more than one team
+    InvalidTeamMember(usize),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "could not read map: {}", err),
+            MapError::Json(err) => write!(f, "invalid map json: {}", err),
+            MapError::UnsupportedVersion(version) => {
+                write!(f, "unsupported map format version {}", version)
+            }
+            MapError::NoPlanets => write!(f, "map has no planets"),
+            MapError::DuplicatePlanetName(name) => write!(f, "duplicate planet name {}", name),
+            MapError::UnknownOwner { planet, owner } => {
+                write!(f, "planet {} is owned by unknown player {}", planet, owner)
+            }
+            MapError::InvalidRules(err) => write!(f, "invalid rules variant: {}", err),
+            MapError::InvalidScenario(err) => write!(f, "invalid scenario: {}", err),
+            MapError::PlayerCountMismatch { expected, actual } => write!(
+                f,
+                "scenario is made for {} players, got {}",
+                expected, actual
+            ),
+            MapError::InvalidTeamMember(player) => {
+                write!(f, "player {} can not be put in this team", player)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<RulesVariantError> for MapError {
+    fn from(err: RulesVariantError) -> Self {
+        MapError::InvalidRules(err)
+    }
+}
+
+impl From<serde_json::Error> for MapError {
+    fn from(err: serde_json::Error) -> Self {
+        MapError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_legacy_map() {
+        let map: Map = serde_json::from_str(
+            r#"{
+                "planets": [
+                    { "name": "a", "x": -3, "y": 0, "owner": 1, "ship_count": 5 },
+                    { "name": "b", "x": 0, "y": 0, "ship_count": 5 },
+                    { "name": "c", "x": 3, "y": 0, "owner": 2, "ship_count": 5 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(map.version, 1);
+        assert_eq!(map.num_players, None);
+        assert!(map.planets.iter().all(|p| p.growth_rate == 1));
+    }
+
+    #[test]
+    fn test_load_v2_map() {
+        let map: Map = serde_json::from_str(
+            r#"{
+                "version": 2,
+                "author": "someone",
+                "num_players": 2,
+                "planets": [
+                    { "name": "a", "x": 0, "y": 0, "owner": 1, "ship_count": 5, "growth_rate": 3 },
+                    { "name": "b", "x": 3, "y": 0, "ship_count": 5 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(map.version, 2);
+        assert_eq!(map.author.as_deref(), Some("someone"));
+        assert_eq!(map.description, None);
+        assert_eq!(map.num_players, Some(2));
+        assert_eq!(map.planets[0].growth_rate, 3);
+        assert_eq!(map.planets[1].growth_rate, 1);
+    }
+
+    fn test_map() -> Map {
+        serde_json::from_str(
+            r#"{
+                "planets": [
+                    { "name": "a", "x": -3, "y": 0, "owner": 1, "ship_count": 5 },
+                    { "name": "b", "x": 0, "y": 0, "ship_count": 5 },
+                    { "name": "c", "x": 3, "y": 0, "owner": 2, "ship_count": 5 }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn inline_config(map: Map) -> Config {
+        Config {
+            map: MapSource::Inline(map),
+            max_turns: 100,
+            combat_rule: CombatRuleConfig::default(),
+            fog_of_war: None,
+            tiebreaks: Vec::new(),
+            variant: None,
+            teams: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_create_state_from_inline_map() {
+        let state = inline_config(test_map()).create_state(2).unwrap();
+        assert_eq!(state.planets.len(), 3);
+        assert_eq!(state.planets[2].owner(), Some(1));
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        let mut map = test_map();
+        map.planets[1].name = "a".to_string();
+        assert!(matches!(
+            inline_config(map).create_state(2),
+            Err(MapError::DuplicatePlanetName(name)) if name == "a"
+        ));
+
+        assert!(matches!(
+            inline_config(test_map()).create_state(1),
+            Err(MapError::UnknownOwner { owner: 2, .. })
+        ));
+
+        let mut map = test_map();
+        map.planets.clear();
+        assert!(matches!(
+            inline_config(map).create_state(2),
+            Err(MapError::NoPlanets)
+        ));
+
+        let config = Config {
+            map: MapSource::File(PathBuf::from("does/not/exist.json")),
+            ..inline_config(test_map())
+        };
+        assert!(matches!(config.create_state(2), Err(MapError::Io(_))));
+    }
+
+    #[test]
+    fn test_rules_variant() {
+        let mut map = test_map();
+        map.rules = Some(RulesVariant {
+            neutral_production: true,
+            ..Default::default()
+        });
+        let state = inline_config(map.clone()).create_state(2).unwrap();
+        assert!(state.variant.neutral_production);
+
+        // the variant of the config takes precedence
+        let config = Config {
+            variant: Some(RulesVariant::default()),
+            ..inline_config(map.clone())
+        };
+        assert!(!config.create_state(2).unwrap().variant.neutral_production);
+
+        map.rules = Some(RulesVariant {
+            fleet_speed: 0.0,
+            ..Default::default()
+        });
+        assert!(matches!(
+            inline_config(map).create_state(2),
+            Err(MapError::InvalidRules(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_state_from_scenario() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{
+                "num_players": 3,
+                "turn_num": 40,
+                "planets": [
+                    { "name": "a", "x": 0, "y": 0, "owner": 1, "ship_count": 5 },
+                    { "name": "b", "x": 3, "y": 0, "owner": 2, "ship_count": 5 },
+                    { "name": "c", "x": 0, "y": 3, "ship_count": 5 }
+                ],
+                "expeditions": [
+                    {
+                        "id": 7, "owner": 3, "ship_count": 20, "origin": "c",
+                        "destination": "a", "turns_remaining": 2
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut config = Config {
+            map: MapSource::Scenario(scenario),
+            ..inline_config(test_map())
+        };
+
+        let state = config.create_state(3).unwrap();
+        assert_eq!(state.turn_num, 40);
+        assert_eq!(state.expeditions.len(), 1);
+        assert_eq!(state.expedition_num, 8);
+        // player 3 has no planets, but still has an expedition in flight
+        assert!(state.players.iter().all(|p| p.alive));
+
+        assert!(matches!(
+            config.create_state(2),
+            Err(MapError::PlayerCountMismatch {
+                expected: 3,
+                actual: 2
+            })
+        ));
+
+        if let MapSource::Scenario(scenario) = &mut config.map {
+            scenario.state.planets[1].name = "a".to_string();
+        }
+        assert!(matches!(
+            config.create_state(3),
+            Err(MapError::DuplicatePlanetName(name)) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn test_teams() {
+        let mut map = test_map();
+        map.planets[1].owner = Some(3);
+        map.planets.push(proto::Planet {
+            name: "d".to_string(),
+            owner: Some(4),
+            ..map.planets[0].clone()
+        });
+        let config = Config {
+            teams: vec![vec![1, 3], vec![2, 4]],
+            ..inline_config(map)
+        };
+        let state = config.create_state(4).unwrap();
+        let teams: Vec<Option<usize>> = state.players.iter().map(|p| p.team).collect();
+        assert_eq!(teams, vec![Some(1), Some(2), Some(1), Some(2)]);
+        assert_eq!(state.teams(), vec![vec![1, 3], vec![2, 4]]);
+
+        for teams in [vec![vec![1, 3], vec![3, 4]], vec![vec![1, 5]]] {
+            let config = Config {
+                teams,
+                ..config.clone()
+            };
+            assert!(matches!(
+                config.create_state(4),
+                Err(MapError::InvalidTeamMember(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::combat::LargestFleetWins;
+use super::protocol as proto;
+use super::rules::{Expedition, Fleet, Planet, Player, PwState};
+use super::variant::RulesVariant;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// An expedition refers to a planet that does not exist.
+    UnknownPlanet(String),
+    /// A planet or expedition is owned by a player that is not in the game.
+    UnknownPlayer(usize),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeserializeError::UnknownPlanet(name) => write!(f, "unknown planet {}", name),
+            DeserializeError::UnknownPlayer(player) => write!(f, "unknown player {}", player),
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// Reconstruct a gamestate from its serialized form.
+/// Player numbers in the given state are expected to be 1-based, as produced
+/// by the serializer.
+/// The serialized form does not describe the combat rule or rules variant;
+/// the standard rules are used.
+pub fn deserialize(
+    state: &proto::State,
+    num_players: usize,
+    max_turns: u64,
+) -> Result<PwState, DeserializeError> {
+    let player_id = |player_num: usize| {
+        if player_num > 0 && player_num <= num_players {
+            Ok(player_num - 1)
+        } else {
+            Err(DeserializeError::UnknownPlayer(player_num))
+        }
+    };
+
+    let mut players: Vec<Player> = (0..num_players)
+        .map(|player_num| Player {
+            id: player_num + 1,
+            alive: false,
+            team: None,
+        })
+        .collect();
+    for (team_idx, team) in state.teams.iter().enumerate() {
+        for &player_num in team {
+            players[player_id(player_num)?].team = Some(team_idx + 1);
+        }
+    }
+
+    let mut planets = Vec::with_capacity(state.planets.len());
+    for (num, planet) in state.planets.iter().enumerate() {
+        let owner = planet.owner.map(player_id).transpose()?;
+        let mut fleets = Vec::new();
+        // an owned planet keeps its owner, even when it holds no ships.
+        if owner.is_some() || planet.ship_count > 0 {
+            fleets.push(Fleet {
+                owner,
+                ship_count: planet.ship_count,
+            });
+        }
+        if let Some(owner_num) = owner {
+            players[owner_num].alive = true;
+        }
+        planets.push(Planet {
+            id: num,
+            name: planet.name.clone(),
+            x: planet.x,
+            y: planet.y,
+            fleets,
+            growth_rate: planet.growth_rate,
+        });
+    }
+
+    let planet_ids: HashMap<&str, usize> =
+        planets.iter().map(|p| (p.name.as_str(), p.id)).collect();
+    let planet_id = |name: &str| {
+        planet_ids
+            .get(name)
+            .cloned()
+            .ok_or_else(|| DeserializeError::UnknownPlanet(name.to_string()))
+    };
+
+    let mut expeditions = Vec::with_capacity(state.expeditions.len());
+    for exp in state.expeditions.iter() {
+        let owner = player_id(exp.owner)?;
+        players[owner].alive = true;
+        expeditions.push(Expedition {
+            id: exp.id,
+            origin: planet_id(&exp.origin)?,
+            target: planet_id(&exp.destination)?,
+            turns_remaining: exp.turns_remaining,
+            fleet: Fleet {
+                owner: Some(owner),
+                ship_count: exp.ship_count,
+            },
+        });
+    }
+
+    // new expeditions should not collide with the existing ones
+    let expedition_num = expeditions.iter().map(|e| e.id + 1).max().unwrap_or(0);
+
+    Ok(PwState {
+        players,
+        planets,
+        expeditions,
+        expedition_num,
+        turn_num: 0,
+        max_turns,
+        combat_rule: Arc::new(LargestFleetWins),
+        variant: RulesVariant::default(),
+    })
+}
+
+/// The serialized form does not record the amount of players, so we derive it
+/// from the highest player number that occurs in the state.
+/// Players that were already eliminated might not be counted.
+pub fn count_players(state: &proto::State) -> usize {
+    let planet_owners = state.planets.iter().filter_map(|p| p.owner);
+    let expedition_owners = state.expeditions.iter().map(|e| e.owner);
+    planet_owners.chain(expedition_owners).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer;
+
+    fn test_state() -> proto::State {
+        proto::State {
+            planets: vec![
+                proto::Planet {
+                    name: "a".to_string(),
+                    x: -3.0,
+                    y: 0.0,
+                    owner: Some(1),
+                    ship_count: 0,
+                    growth_rate: 1,
+                    hidden: false,
+                },
+                proto::Planet {
+                    name: "b".to_string(),
+                    x: 0.0,
+                    y: 0.0,
+                    owner: None,
+                    ship_count: 5,
+                    growth_rate: 2,
+                    hidden: false,
+                },
+                proto::Planet {
+                    name: "c".to_string(),
+                    x: 3.0,
+                    y: 0.0,
+                    owner: Some(2),
+                    ship_count: 7,
+                    growth_rate: 1,
+                    hidden: false,
+                },
+            ],
+            expeditions: vec![proto::Expedition {
+                id: 4,
+                ship_count: 5,
+                origin: "a".to_string(),
+                destination: "b".to_string(),
+                owner: 1,
+                turns_remaining: 2,
+            }],
+            teams: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_roundtrip() {
+        let state = test_state();
+        let pw_state = deserialize(&state, 2, 100).unwrap();
+        assert_eq!(pw_state.expedition_num, 5);
+        assert_eq!(serializer::serialize(&pw_state), state);
+    }
+
+    #[test]
+    fn test_deserialize_unknown_player() {
+        let state = test_state();
+        let res = deserialize(&state, 1, 100);
+        assert_eq!(res.err(), Some(DeserializeError::UnknownPlayer(2)));
+    }
+}
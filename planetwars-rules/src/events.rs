@@ -0,0 +1,38 @@
+/// Something that happened while a turn was resolved.
+/// Planets are referred to by name and players by their (1-based) player number,
+/// like in the serialized game state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TurnEvent {
+    /// An expedition reached its destination
+    FleetArrived {
+        expedition_id: u64,
+        origin: String,
+        destination: String,
+        owner: Option<usize>,
+        ship_count: u64,
+    },
+    /// Fleets of opposing sides fought at a planet
+    Battle {
+        planet: String,
+        participants: Vec<BattleParticipant>,
+    },
+    /// A planet changed owner.
+    /// The new owner is `None` when no fleet survived the battle for it.
+    PlanetCaptured {
+        planet: String,
+        previous_owner: Option<usize>,
+        new_owner: Option<usize>,
+    },
+    /// A player has no planets and no expeditions left
+    PlayerEliminated { player: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BattleParticipant {
+    pub owner: Option<usize>,
+    /// Ships present when the battle started
+    pub ship_count: u64,
+    /// Ships lost in the battle
+    pub losses: u64,
+}
@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::config::{Map, MAP_FORMAT_VERSION};
+use super::protocol as proto;
+
+/// How a generated map is made fair for all players.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symmetry {
+    /// Every player gets a rotated copy of the same sector.
+    Rotational,
+    /// The map is mirrored in the vertical axis. Only valid for two players.
+    Mirror,
+}
+
+/// Parameters for generating a map.
+/// Generating a map twice from the same parameters yields the same map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapGenParams {
+    pub seed: u64,
+    pub num_players: usize,
+    pub symmetry: Symmetry,
+    /// Amount of neutral planets generated for every player
+    pub neutral_planets_per_player: usize,
+    /// Radius of the map
+    pub spread: f64,
+    /// Amount of ships each player starts with on their home planet
+    pub home_ships: u64,
+    /// Neutral planets start with between 1 and this many ships
+    pub max_neutral_ships: u64,
+}
+
+impl Default for MapGenParams {
+    // Maps are reproduced from their seed and these defaults,
+    // so changing them changes previously generated maps.
+    fn default() -> Self {
+        MapGenParams {
+            seed: 0,
+            num_players: 2,
+            symmetry: Symmetry::Rotational,
+            neutral_planets_per_player: 5,
+            spread: 12.0,
+            home_ships: 10,
+            max_neutral_ships: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerateMapError {
+    NoPlayers,
+    /// Mirror symmetry is only fair for two players
+    MirrorRequiresTwoPlayers,
+}
+
+impl fmt::Display for GenerateMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerateMapError::NoPlayers => write!(f, "a map needs at least one player"),
+            GenerateMapError::MirrorRequiresTwoPlayers => {
+                write!(f, "mirror symmetry requires exactly two players")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GenerateMapError {}
+
+/// Minimal distance between two planets
+const MIN_PLANET_DISTANCE: f64 = 2.0;
+/// How often we try to place a planet before giving up on it
+const MAX_PLACEMENT_ATTEMPTS: usize = 100;
+
+const NAME_SYLLABLES: &[&str] = &[
+    "al", "be", "ca", "do", "el", "fi", "ga", "hu", "io", "ka", "lo", "mi", "nu", "or", "pa", "qu",
+    "ri", "sa", "te", "ul", "ve", "xi", "yo", "ze",
+];
+
+pub fn generate_map(params: &MapGenParams) -> Result<Map, GenerateMapError> {
+    if params.num_players == 0 {
+        return Err(GenerateMapError::NoPlayers);
+    }
+    if params.symmetry == Symmetry::Mirror && params.num_players != 2 {
+        return Err(GenerateMapError::MirrorRequiresTwoPlayers);
+    }
+
+    let mut generator = MapGenerator {
+        params,
+        rng: SplitMix64::new(params.seed),
+        planets: Vec::new(),
+        names: HashSet::new(),
+    };
+    generator.place_home_planets();
+    for _ in 0..params.neutral_planets_per_player {
+        generator.place_neutral_planets();
+    }
+
+    Ok(Map {
+        version: MAP_FORMAT_VERSION,
+        author: None,
+        description: Some(format!("generated from seed {}", params.seed)),
+        num_players: Some(params.num_players),
+        rules: None,
+        planets: generator.planets,
+    })
+}
+
+struct MapGenerator<'a> {
+    params: &'a MapGenParams,
+    rng: SplitMix64,
+    planets: Vec<proto::Planet>,
+    names: HashSet<String>,
+}
+
+impl<'a> MapGenerator<'a> {
+    /// All symmetric images of the given point, one for every player.
+    fn images(&self, (x, y): (f64, f64)) -> Vec<(f64, f64)> {
+        match self.params.symmetry {
+            Symmetry::Mirror => vec![(x, y), (-x, y)],
+            Symmetry::Rotational => (0..self.params.num_players)
+                .map(|player_num| {
+                    let angle = 2.0 * PI * player_num as f64 / self.params.num_players as f64;
+                    let (sin, cos) = angle.sin_cos();
+                    (x * cos - y * sin, x * sin + y * cos)
+                })
+                .collect(),
+        }
+    }
+
+    fn place_home_planets(&mut self) {
+        let radius = self.params.spread * 0.75;
+        let position = match self.params.symmetry {
+            Symmetry::Mirror => (-radius, 0.0),
+            Symmetry::Rotational => (radius, 0.0),
+        };
+        for (player_num, image) in self.images(position).into_iter().enumerate() {
+            self.add_planet(image, Some(player_num + 1), self.params.home_ships);
+        }
+    }
+
+    fn place_neutral_planets(&mut self) {
+        for _ in 0..MAX_PLACEMENT_ATTEMPTS {
+            let position = self.random_position();
+            let images = self.images(position);
+            if self.has_room_for(&images) {
+                let ship_count = 1 + self.rng.next_below(self.params.max_neutral_ships.max(1));
+                for image in images {
+                    self.add_planet(image, None, ship_count);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Pick a random position in the sector of the first player.
+    fn random_position(&mut self) -> (f64, f64) {
+        let (min_angle, max_angle) = match self.params.symmetry {
+            Symmetry::Mirror => (0.5 * PI, 1.5 * PI),
+            Symmetry::Rotational => {
+                let half_sector = PI / self.params.num_players as f64;
+                (-half_sector, half_sector)
+            }
+        };
+        let angle = min_angle + (max_angle - min_angle) * self.rng.next_f64();
+        // take the square root to get a uniform distribution over the area
+        let radius = self.params.spread * self.rng.next_f64().sqrt();
+        (radius * angle.cos(), radius * angle.sin())
+    }
+
+    fn has_room_for(&self, positions: &[(f64, f64)]) -> bool {
+        let occupied = self.planets.iter().map(|p| (p.x, p.y));
+        let is_free = |(x, y): (f64, f64), (ox, oy): (f64, f64)| {
+            (x - ox).powi(2) + (y - oy).powi(2) >= MIN_PLANET_DISTANCE.powi(2)
+        };
+
+        positions.iter().enumerate().all(|(i, &pos)| {
+            occupied.clone().all(|other| is_free(pos, other))
+                && positions[i + 1..].iter().all(|&other| is_free(pos, other))
+        })
+    }
+
+    fn add_planet(&mut self, (x, y): (f64, f64), owner: Option<usize>, ship_count: u64) {
+        let name = self.generate_name();
+        self.planets.push(proto::Planet {
+            name,
+            x,
+            y,
+            owner,
+            ship_count,
+            growth_rate: 1,
+            hidden: false,
+        });
+    }
+
+    fn generate_name(&mut self) -> String {
+        loop {
+            let num_syllables = 2 + self.rng.next_below(2);
+            let name: String = (0..num_syllables)
+                .map(|_| NAME_SYLLABLES[self.rng.next_below(NAME_SYLLABLES.len() as u64) as usize])
+                .collect();
+            if self.names.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
+
+/// A small, fast pseudo-random number generator.
+/// We implement this ourselves so that generated maps are guaranteed to
+/// stay the same for the same seed.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed float in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in [0, bound). The slight bias is acceptable for map generation.
+    fn next_below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance_from_center(planet: &proto::Planet) -> f64 {
+        (planet.x.powi(2) + planet.y.powi(2)).sqrt()
+    }
+
+    #[test]
+    fn test_generate_deterministic() {
+        let params = MapGenParams {
+            seed: 42,
+            ..Default::default()
+        };
+        let a = generate_map(&params).unwrap();
+        let b = generate_map(&params).unwrap();
+        assert_eq!(a.planets, b.planets);
+
+        let c = generate_map(&MapGenParams {
+            seed: 43,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_ne!(a.planets, c.planets);
+    }
+
+    #[test]
+    fn test_generate_rotational() {
+        let params = MapGenParams {
+            seed: 7,
+            num_players: 3,
+            ..Default::default()
+        };
+        let map = generate_map(&params).unwrap();
+        assert_eq!(map.num_players, Some(3));
+        assert_eq!(map.planets.len() % 3, 0);
+
+        let names: HashSet<&str> = map.planets.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names.len(), map.planets.len());
+
+        // planets are generated in groups of symmetric images
+        for group in map.planets.chunks(3) {
+            let owners: Vec<Option<usize>> = group.iter().map(|p| p.owner).collect();
+            assert!(owners == [Some(1), Some(2), Some(3)] || owners == [None, None, None]);
+            for planet in group {
+                assert_eq!(planet.ship_count, group[0].ship_count);
+                assert!(
+                    (distance_from_center(planet) - distance_from_center(&group[0])).abs() < 1e-9
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_mirror() {
+        let params = MapGenParams {
+            seed: 3,
+            symmetry: Symmetry::Mirror,
+            ..Default::default()
+        };
+        let map = generate_map(&params).unwrap();
+        for pair in map.planets.chunks(2) {
+            assert_eq!(pair[0].x, -pair[1].x);
+            assert_eq!(pair[0].y, pair[1].y);
+        }
+
+        let res = generate_map(&MapGenParams {
+            num_players: 3,
+            ..params
+        });
+        assert_eq!(res.err(), Some(GenerateMapError::MirrorRequiresTwoPlayers));
+    }
+}
@@ -2,58 +2,184 @@
 extern crate serde;
 extern crate serde_json;
 
+pub mod classic;
+pub mod combat;
 pub mod config;
+pub mod deserializer;
+pub mod events;
+pub mod generator;
+pub mod outcome;
 pub mod protocol;
 pub mod rules;
+pub mod scenario;
 pub mod serializer;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod variant;
+pub mod visibility;
 
+pub use combat::{CombatRule, CombatRuleConfig};
 pub use config::Config as PwConfig;
+pub use config::{MapError, MapSource};
+pub use deserializer::DeserializeError;
+pub use events::TurnEvent;
+pub use outcome::{Side, Tiebreak, Victory, WinReason};
 pub use protocol::CommandError;
 pub use rules::{Dispatch, PwState};
+pub use scenario::Scenario;
 use std::collections::HashMap;
+use std::sync::Arc;
+pub use variant::RulesVariant;
+use variant::ZeroDistance;
+pub use visibility::FogOfWar;
 
+#[derive(Debug, Clone)]
 pub struct PlanetWars {
     /// Game state
     state: rules::PwState,
     /// Map planet names to their ids
     planet_map: HashMap<String, usize>,
+    /// Limits what players can see, if set
+    fog_of_war: Option<FogOfWar>,
+    /// Decide the winner when the turn limit is reached
+    tiebreaks: Vec<Tiebreak>,
 }
 
 impl PlanetWars {
-    pub fn create(config: PwConfig, num_players: usize) -> Self {
-        let state = config.create_state(num_players);
+    pub fn create(config: PwConfig, num_players: usize) -> Result<Self, MapError> {
+        let state = config.create_state(num_players)?;
+        let mut planet_wars = Self::from_pw_state(state);
+        planet_wars.fog_of_war = config.fog_of_war;
+        planet_wars.tiebreaks = config.tiebreaks;
+        Ok(planet_wars)
+    }
+
+    /// Resume a game from a serialized state, such as one found in a match log
+    /// or the state a bot receives.
+    pub fn from_state(
+        state: &protocol::State,
+        num_players: usize,
+        max_turns: u64,
+    ) -> Result<Self, DeserializeError> {
+        let state = deserializer::deserialize(state, num_players, max_turns)?;
+        Ok(Self::from_pw_state(state))
+    }
 
+    /// Resume a game from a game state, such as one obtained from `state()`.
+    pub fn from_pw_state(state: PwState) -> Self {
         let planet_map = state
             .planets
             .iter()
             .map(|p| (p.name.clone(), p.id))
             .collect();
 
-        PlanetWars { state, planet_map }
+        PlanetWars {
+            state,
+            planet_map,
+            fog_of_war: None,
+            tiebreaks: Vec::new(),
+        }
     }
 
-    /// Proceed to next turn
-    pub fn step(&mut self) {
+    /// Proceed to next turn, returning what happened during it
+    pub fn step(&mut self) -> Vec<TurnEvent> {
         self.state.repopulate();
-        self.state.step();
+        self.state.step()
+    }
+
+    /// Play a single turn, in which the given players issue the given commands.
+    /// Commands are given as (player_num, command) pairs, and are executed in order.
+    /// Returns the result of every command; invalid commands are not executed.
+    pub fn play_turn(
+        &mut self,
+        commands: &[(usize, protocol::Command)],
+    ) -> Vec<Result<(), CommandError>> {
+        let results = commands
+            .iter()
+            .map(|(player_num, cmd)| self.execute_command(*player_num, cmd))
+            .collect();
+        self.step();
+        results
+    }
+
+    /// Proceed the given amount of turns without issuing any commands,
+    /// stopping early when the game finishes.
+    /// Returns the amount of turns that were played.
+    pub fn step_turns(&mut self, num_turns: u64) -> u64 {
+        let mut turns_played = 0;
+        while turns_played < num_turns && !self.is_finished() {
+            self.step();
+            turns_played += 1;
+        }
+        turns_played
+    }
+
+    /// Remove a player from the game, such as a player whose bot could not be started.
+    /// Its planets become neutral.
+    pub fn forfeit(&mut self, player_id: usize) {
+        self.state.forfeit(player_id);
     }
 
     pub fn is_finished(&self) -> bool {
         self.state.is_finished()
     }
 
+    /// The winner of the game, if there is one
+    pub fn victory(&self) -> Option<Victory> {
+        outcome::victory(&self.state, &self.tiebreaks)
+    }
+
     pub fn serialize_state(&self) -> protocol::State {
         serializer::serialize(&self.state)
     }
 
+    /// Serialize the state as the given player gets to see it.
+    /// Player numbers are rotated so that this player is player 1.
     pub fn serialize_player_state(&self, player_id: usize) -> protocol::State {
-        serializer::serialize_rotated(&self.state, player_id - 1)
+        let mut state = serializer::serialize_rotated(&self.state, player_id - 1);
+        if let Some(fog_of_war) = &self.fog_of_war {
+            fog_of_war.apply(&mut state, &self.state.variant);
+        }
+        state
+    }
+
+    /// The number the given player has in the states sent to the viewing player,
+    /// who is always player 1.
+    pub fn rotated_player_num(&self, viewer_id: usize, player_id: usize) -> usize {
+        let num_players = self.state.players.len();
+        (player_id + num_players - viewer_id) % num_players + 1
     }
 
     pub fn state(&self) -> &PwState {
         &self.state
     }
 
+    pub fn fog_of_war(&self) -> Option<&FogOfWar> {
+        self.fog_of_war.as_ref()
+    }
+
+    pub fn set_fog_of_war(&mut self, fog_of_war: Option<FogOfWar>) {
+        self.fog_of_war = fog_of_war;
+    }
+
+    pub fn set_tiebreaks(&mut self, tiebreaks: Vec<Tiebreak>) {
+        self.tiebreaks = tiebreaks;
+    }
+
+    pub fn rules_variant(&self) -> &RulesVariant {
+        &self.state.variant
+    }
+
+    pub fn set_rules_variant(&mut self, variant: RulesVariant) {
+        self.state.variant = variant;
+    }
+
+    /// Replace the rule used for resolving combat,
+    /// allowing for rules that are not built into this crate.
+    pub fn set_combat_rule(&mut self, combat_rule: Arc<dyn CombatRule>) {
+        self.state.combat_rule = combat_rule;
+    }
+
     /// Execute a command
     pub fn execute_command(
         &mut self,
@@ -95,6 +221,14 @@ impl PlanetWars {
             return Err(CommandError::ZeroShipMove);
         }
 
+        if self.state.variant.zero_distance == ZeroDistance::Reject {
+            let origin = &self.state.planets[origin_id];
+            let target = &self.state.planets[target_id];
+            if self.state.variant.travel_turns(origin, target) == 0 {
+                return Err(CommandError::ZeroDistanceMove);
+            }
+        }
+
         Ok(Dispatch {
             origin: origin_id,
             target: target_id,
@@ -109,3 +243,77 @@ impl PlanetWars {
         self.state.dispatch(dispatch);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> protocol::State {
+        test_util::test_state(&[("a", -2.0, Some(1)), ("b", 0.0, None), ("c", 2.0, Some(2))])
+    }
+
+    fn command(origin: &str, destination: &str, ship_count: u64) -> protocol::Command {
+        protocol::Command {
+            origin: origin.to_string(),
+            destination: destination.to_string(),
+            ship_count,
+        }
+    }
+
+    #[test]
+    fn test_simulate_from_state() {
+        let mut state = test_state();
+        state.planets[1].ship_count = 2;
+        let num_players = deserializer::count_players(&state);
+        let game = PlanetWars::from_state(&state, num_players, 100).unwrap();
+
+        let mut simulation = game.clone();
+        let results = simulation.play_turn(&[(1, command("a", "b", 5)), (2, command("c", "b", 6))]);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(CommandError::NotEnoughShips)));
+        assert_eq!(simulation.step_turns(10), 10);
+
+        // player 1 took the neutral planet, and the original game is unaffected
+        assert_eq!(simulation.state().planets[1].owner(), Some(0));
+        assert_eq!(game.serialize_state(), state);
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut game = PlanetWars::from_state(&test_state(), 2, 100).unwrap();
+        game.play_turn(&[(1, command("a", "c", 4))]);
+
+        let snapshot = serde_json::to_string(game.state()).unwrap();
+        let pw_state: PwState = serde_json::from_str(&snapshot).unwrap();
+        let mut resumed = PlanetWars::from_pw_state(pw_state);
+
+        game.step_turns(5);
+        resumed.step_turns(5);
+        assert_eq!(resumed.serialize_state(), game.serialize_state());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_combat_rule() {
+        let mut game = PlanetWars::from_state(&test_state(), 2, 100).unwrap();
+        game.set_combat_rule(CombatRuleConfig::DefenderBonus { bonus_percent: 50 }.build());
+
+        let snapshot = serde_json::to_string(game.state()).unwrap();
+        let pw_state: PwState = serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(
+            pw_state.combat_rule.config(),
+            Some(CombatRuleConfig::DefenderBonus { bonus_percent: 50 })
+        );
+    }
+
+    #[test]
+    fn test_rotated_player_num() {
+        let mut state = test_state();
+        state.planets[1].owner = Some(3);
+        let game = PlanetWars::from_state(&state, 3, 100).unwrap();
+        assert_eq!(game.rotated_player_num(2, 2), 1);
+        assert_eq!(game.rotated_player_num(2, 3), 2);
+        assert_eq!(game.rotated_player_num(2, 1), 3);
+        // consistent with the serialized player state
+        assert_eq!(game.serialize_player_state(2).planets[0].owner, Some(3));
+    }
+}
@@ -0,0 +1,182 @@
+use super::rules::PwState;
+
+/// A criterion for deciding the winner of a game that reached its turn limit
+/// with more than one player alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tiebreak {
+    /// The most ships, on planets and in expeditions
+    Ships,
+    /// The most planets owned
+    Planets,
+    /// The highest total growth rate of owned planets
+    Production,
+}
+
+/// Why a player won the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WinReason {
+    /// All other players were eliminated
+    Elimination,
+    /// The turn limit was reached, and the player came out on top of this tiebreak
+    Tiebreak(Tiebreak),
+}
+
+/// A player, or a team of players that plays together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Player(usize),
+    /// Team number, starting at 1
+    Team(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Victory {
+    pub winner: Side,
+    pub reason: WinReason,
+}
+
+/// Decide who won the game, if anyone.
+/// When the turn limit is reached, the tiebreaks are applied in order
+/// until one of them singles out a side.
+/// A team shares its victory, and its score is the sum of the scores of its players.
+pub fn victory(state: &PwState, tiebreaks: &[Tiebreak]) -> Option<Victory> {
+    let mut candidates = state.living_sides();
+    if candidates.len() == 1 {
+        return Some(Victory {
+            winner: candidates[0],
+            reason: WinReason::Elimination,
+        });
+    }
+    if candidates.is_empty() || state.turn_num < state.max_turns {
+        return None;
+    }
+
+    for &tiebreak in tiebreaks {
+        let best_score = candidates
+            .iter()
+            .map(|&side| side_score(state, side, tiebreak))
+            .max()?;
+        candidates.retain(|&side| side_score(state, side, tiebreak) == best_score);
+        if candidates.len() == 1 {
+            return Some(Victory {
+                winner: candidates[0],
+                reason: WinReason::Tiebreak(tiebreak),
+            });
+        }
+    }
+    None
+}
+
+fn side_score(state: &PwState, side: Side, tiebreak: Tiebreak) -> u64 {
+    state
+        .players
+        .iter()
+        .filter(|p| p.side() == side)
+        .map(|p| tiebreak_score(state, p.id, tiebreak))
+        .sum()
+}
+
+/// The score of a player for the given tiebreak; higher is better.
+pub fn tiebreak_score(state: &PwState, player_id: usize, tiebreak: Tiebreak) -> u64 {
+    // player ids start at 1, owners at 0
+    let owner = Some(player_id - 1);
+    let owned_planets = state.planets.iter().filter(|p| p.owner() == owner);
+    match tiebreak {
+        Tiebreak::Ships => {
+            let expedition_ships = state
+                .expeditions
+                .iter()
+                .filter(|e| e.fleet.owner == owner)
+                .map(|e| e.fleet.ship_count);
+            owned_planets
+                .map(|p| p.ship_count())
+                .chain(expedition_ships)
+                .sum()
+        }
+        Tiebreak::Planets => owned_planets.count() as u64,
+        Tiebreak::Production => owned_planets.map(|p| p.growth_rate).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserializer::deserialize;
+    use crate::protocol as proto;
+    use crate::rules::Player;
+
+    fn test_state() -> PwState {
+        let planet =
+            |name: &str, owner: Option<usize>, ship_count: u64, growth_rate: u64| proto::Planet {
+                name: name.to_string(),
+                x: 0.0,
+                y: 0.0,
+                owner,
+                ship_count,
+                growth_rate,
+                hidden: false,
+            };
+        let state = proto::State {
+            planets: vec![
+                planet("a", Some(1), 10, 1),
+                planet("b", Some(1), 5, 1),
+                planet("c", Some(2), 15, 3),
+            ],
+            expeditions: vec![],
+            teams: vec![],
+        };
+        deserialize(&state, 2, 100).unwrap()
+    }
+
+    #[test]
+    fn test_tiebreaks() {
+        let mut state = test_state();
+        let tiebreaks = [Tiebreak::Ships, Tiebreak::Planets, Tiebreak::Production];
+        // the game is not over yet
+        assert_eq!(victory(&state, &tiebreaks), None);
+
+        state.turn_num = 100;
+        // both players have 15 ships, so this is decided on planets
+        assert_eq!(
+            victory(&state, &tiebreaks),
+            Some(Victory {
+                winner: Side::Player(1),
+                reason: WinReason::Tiebreak(Tiebreak::Planets),
+            })
+        );
+        assert_eq!(
+            victory(&state, &[Tiebreak::Production]),
+            Some(Victory {
+                winner: Side::Player(2),
+                reason: WinReason::Tiebreak(Tiebreak::Production),
+            })
+        );
+        assert_eq!(victory(&state, &[Tiebreak::Ships]), None);
+    }
+
+    #[test]
+    fn test_team_victory() {
+        let mut state = test_state();
+        state.players.push(Player {
+            id: 3,
+            alive: true,
+            team: None,
+        });
+        for (player, team) in state.players.iter_mut().zip([1, 2, 1]) {
+            player.team = Some(team);
+        }
+        // the only player of team 2 was eliminated
+        state.players[1].alive = false;
+        assert_eq!(state.living_sides(), vec![Side::Team(1)]);
+        assert_eq!(
+            victory(&state, &[]),
+            Some(Victory {
+                winner: Side::Team(1),
+                reason: WinReason::Elimination,
+            })
+        );
+    }
+}
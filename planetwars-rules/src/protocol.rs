@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use super::outcome::WinReason;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Expedition {
     pub id: u64,
     pub ship_count: u64,
@@ -8,19 +10,34 @@ pub struct Expedition {
     pub turns_remaining: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Planet {
     pub ship_count: u64,
     pub x: f64,
     pub y: f64,
     pub owner: Option<usize>,
     pub name: String,
+    /// Amount of ships this planet produces each turn, when owned by a player.
+    #[serde(default = "default_growth_rate")]
+    pub growth_rate: u64,
+    /// Set when this planet is outside of the player's vision.
+    /// Its owner and ship count are unknown in that case.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+fn default_growth_rate() -> u64 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Action {
     #[serde(rename = "moves")]
     pub commands: Vec<Command>,
+    /// The `request_id` of the message this action responds to.
+    /// Bots can echo it so that a late action is not mistaken for the next one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,15 +47,99 @@ pub struct Command {
     pub ship_count: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct State {
     pub planets: Vec<Planet>,
     pub expeditions: Vec<Expedition>,
+    /// The player numbers in every team, where team `n` is found at index `n - 1`.
+    /// Players in the same team are allies. Empty when every player plays for themselves.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<Vec<usize>>,
 }
 
+/// Sent to a bot before the game starts.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameInfo {
-    pub players: Vec<String>,
+    /// Player number of the bot in this match.
+    /// The states a bot receives are rotated so that it is always player 1.
+    pub player_num: usize,
+    /// Player names, in the order they are numbered in the states the bot receives.
+    pub players: Vec<Option<String>>,
+    pub max_turns: u64,
+    /// How long a bot may take to respond to a state, in milliseconds
+    pub turn_timeout_ms: u64,
+}
+
+/// Sent to a bot when the game has ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameOver {
+    /// The winning player, numbered as in the states the bot received.
+    /// Not set when the game ended in a draw or was won by a team.
+    pub winner: Option<usize>,
+    /// The winning team, in games played by teams
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub winning_team: Option<usize>,
+    /// Why the winner won, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub win_reason: Option<WinReason>,
+    pub turn_num: u64,
+}
+
+/// A gamestate, along with information about the turn that is being played.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnState {
+    #[serde(flatten)]
+    pub state: State,
+    /// Amount of turns that have been played so far
+    pub turn_num: u64,
+    pub max_turns: u64,
+    /// How long the bot has left to respond to this state, in milliseconds
+    pub deadline_ms: u64,
+}
+
+/// Problems with the response a bot gave in the previous turn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandFeedback {
+    /// Commands that were not executed, along with the reason why
+    pub rejected_commands: Vec<RejectedCommand>,
+    /// Describes why the response could not be parsed, if it could not
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedCommand {
+    #[serde(flatten)]
+    pub command: Command,
+    pub error: CommandError,
+}
+
+/// A message to a bot, tagged with its type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    GameInfo(GameInfo),
+    State(State),
+    Turn(TurnState),
+    GameOver(GameOver),
+}
+
+/// The protocol a bot speaks.
+/// Later versions include all messages of the versions before them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolVersion {
+    /// The bot receives a bare `State` every turn.
+    #[default]
+    V1,
+    /// The bot receives `ServerMessage`s: a `GameInfo` before the first turn,
+    /// a `State` every turn and a `GameOver` when the game ends.
+    /// `GameInfo` and `GameOver` should be acknowledged with a single line,
+    /// the content of which is ignored.
+    V2,
+    /// Like `V2`, but every turn the bot receives a `Turn` instead of a `State`.
+    /// Every message carries a `request_id`, which the bot may echo in its response.
+    V3,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,4 +149,37 @@ pub enum CommandError {
     ZeroShipMove,
     OriginDoesNotExist,
     DestinationDoesNotExist,
+    /// The destination is at distance 0, which the rules variant does not allow
+    ZeroDistanceMove,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_turn_message() {
+        let message = ServerMessage::Turn(TurnState {
+            state: State {
+                planets: Vec::new(),
+                expeditions: Vec::new(),
+                teams: Vec::new(),
+            },
+            turn_num: 3,
+            max_turns: 500,
+            deadline_ms: 1000,
+        });
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "turn",
+                "planets": [],
+                "expeditions": [],
+                "turn_num": 3,
+                "max_turns": 500,
+                "deadline_ms": 1000,
+            })
+        );
+    }
 }
@@ -1,5 +1,16 @@
+use std::mem;
+use std::sync::Arc;
+
+use super::combat::{CombatRule, LargestFleetWins};
+use super::events::{BattleParticipant, TurnEvent};
+use super::outcome::Side;
+use super::variant::RulesVariant;
+
 /// The planet wars game rules.
-#[derive(Debug)]
+/// Cloning a state is cheap enough to use it for searching game trees.
+/// The combat rule is serialized as its `CombatRuleConfig`,
+/// so states using a custom combat rule cannot be serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PwState {
     pub players: Vec<Player>,
     pub planets: Vec<Planet>,
@@ -9,30 +20,79 @@ pub struct PwState {
     pub expedition_num: u64,
     pub turn_num: u64,
     pub max_turns: u64,
+    #[serde(with = "combat_rule_serde", default = "default_combat_rule")]
+    pub combat_rule: Arc<dyn CombatRule>,
+    #[serde(default)]
+    pub variant: RulesVariant,
+}
+
+fn default_combat_rule() -> Arc<dyn CombatRule> {
+    Arc::new(LargestFleetWins)
+}
+
+mod combat_rule_serde {
+    use serde::ser::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::Arc;
+
+    use super::CombatRule;
+    use crate::combat::CombatRuleConfig;
+
+    pub fn serialize<S: Serializer>(
+        combat_rule: &Arc<dyn CombatRule>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match combat_rule.config() {
+            Some(config) => config.serialize(serializer),
+            None => Err(S::Error::custom(
+                "a custom combat rule cannot be serialized",
+            )),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<dyn CombatRule>, D::Error> {
+        CombatRuleConfig::deserialize(deserializer).map(|config| config.build())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub id: usize,
     pub alive: bool,
+    /// The team this player plays in, numbered from 1.
+    /// Players without a team play for themselves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<usize>,
+}
+
+impl Player {
+    pub fn side(&self) -> Side {
+        match self.team {
+            Some(team) => Side::Team(team),
+            None => Side::Player(self.id),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Fleet {
     pub owner: Option<usize>,
     pub ship_count: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Planet {
     pub id: usize,
     pub name: String,
     pub fleets: Vec<Fleet>,
     pub x: f64,
     pub y: f64,
+    pub growth_rate: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Expedition {
     pub id: u64,
     pub origin: usize,
@@ -41,7 +101,7 @@ pub struct Expedition {
     pub turns_remaining: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dispatch {
     pub origin: usize,
     pub target: usize,
@@ -50,7 +110,10 @@ pub struct Dispatch {
 
 impl PwState {
     pub fn dispatch(&mut self, dispatch: &Dispatch) {
-        let distance = self.planets[dispatch.origin].distance(&self.planets[dispatch.target]);
+        let distance = self.variant.travel_turns(
+            &self.planets[dispatch.origin],
+            &self.planets[dispatch.target],
+        );
 
         let origin = &mut self.planets[dispatch.origin];
         origin.fleets[0].ship_count -= dispatch.ship_count;
@@ -72,28 +135,63 @@ impl PwState {
     }
 
     // Play one step of the game
-    pub fn step(&mut self) {
+    pub fn step(&mut self) -> Vec<TurnEvent> {
         self.turn_num += 1;
+        let mut events = Vec::new();
 
         // Initially mark all players dead, re-marking them as alive once we
         // encounter a sign of life.
+        let was_alive = self.players.iter().map(|p| p.alive).collect::<Vec<_>>();
         for player in self.players.iter_mut() {
             player.alive = false;
         }
 
-        self.step_expeditions();
-        self.resolve_combat();
+        // Planets that are still occupied at this point are defended by their
+        // current fleet; arriving fleets will be added after it.
+        let defended = self
+            .planets
+            .iter()
+            .map(|p| !p.fleets.is_empty())
+            .collect::<Vec<_>>();
+        let previous_owners = self.planets.iter().map(|p| p.owner()).collect::<Vec<_>>();
+
+        self.step_expeditions(&mut events);
+        self.resolve_combat(&defended, &mut events);
+
+        for planet in self.planets.iter() {
+            let previous_owner = previous_owners[planet.id];
+            if planet.owner() != previous_owner {
+                events.push(TurnEvent::PlanetCaptured {
+                    planet: planet.name.clone(),
+                    previous_owner: previous_owner.map(player_num),
+                    new_owner: planet.owner().map(player_num),
+                });
+            }
+        }
+        for (player, &was_alive) in self.players.iter().zip(was_alive.iter()) {
+            if was_alive && !player.alive {
+                events.push(TurnEvent::PlayerEliminated { player: player.id });
+            }
+        }
+        events
     }
 
     pub fn repopulate(&mut self) {
         for planet in self.planets.iter_mut() {
-            if planet.owner().is_some() {
-                planet.fleets[0].ship_count += 1;
+            let produces = match planet.fleets.first() {
+                Some(fleet) => fleet.owner.is_some() || self.variant.neutral_production,
+                None => false,
+            };
+            if produces {
+                let fleet = &mut planet.fleets[0];
+                fleet.ship_count += self
+                    .variant
+                    .production(fleet.ship_count, planet.growth_rate);
             }
         }
     }
 
-    fn step_expeditions(&mut self) {
+    fn step_expeditions(&mut self, events: &mut Vec<TurnEvent>) {
         let mut i = 0;
         let exps = &mut self.expeditions;
         while i < exps.len() {
@@ -101,6 +199,13 @@ impl PwState {
             if exps[i].turns_remaining <= 1 {
                 // remove expedition from expeditions, and add to fleet
                 let exp = exps.swap_remove(i);
+                events.push(TurnEvent::FleetArrived {
+                    expedition_id: exp.id,
+                    origin: self.planets[exp.origin].name.clone(),
+                    destination: self.planets[exp.target].name.clone(),
+                    owner: exp.fleet.owner.map(player_num),
+                    ship_count: exp.fleet.ship_count,
+                });
                 let planet = &mut self.planets[exp.target];
                 planet.orbit(exp.fleet);
             } else {
@@ -116,9 +221,22 @@ impl PwState {
         }
     }
 
-    fn resolve_combat(&mut self) {
+    fn resolve_combat(&mut self, defended: &[bool], events: &mut Vec<TurnEvent>) {
+        let sides = self.players.iter().map(Player::side).collect::<Vec<_>>();
         for planet in self.planets.iter_mut() {
-            planet.resolve_combat();
+            planet.merge_allies(&sides);
+            let defender = if defended[planet.id] { Some(0) } else { None };
+            let participants = planet.fleets.clone();
+            planet.resolve_combat(self.combat_rule.as_ref(), defender);
+            if participants.len() > 1 {
+                events.push(TurnEvent::Battle {
+                    planet: planet.name.clone(),
+                    participants: participants
+                        .iter()
+                        .map(|fleet| battle_participant(fleet, planet.fleets.first()))
+                        .collect(),
+                });
+            }
             if let Some(owner_num) = planet.owner() {
                 // owner owns a planet; this is a sign of life.
                 self.players[owner_num].alive = true;
@@ -127,8 +245,48 @@ impl PwState {
     }
 
     pub fn is_finished(&self) -> bool {
-        let remaining = self.players.iter().filter(|p| p.alive).count();
-        remaining < 2 || self.turn_num >= self.max_turns
+        self.living_sides().len() < 2 || self.turn_num >= self.max_turns
+    }
+
+    /// The sides that still have a living player
+    pub fn living_sides(&self) -> Vec<Side> {
+        let mut sides: Vec<Side> = self
+            .players
+            .iter()
+            .filter(|p| p.alive)
+            .map(Player::side)
+            .collect();
+        sides.sort();
+        sides.dedup();
+        sides
+    }
+
+    /// The ids of the players in every team, indexed by team number minus one.
+    /// Empty when nobody plays in a team.
+    pub fn teams(&self) -> Vec<Vec<usize>> {
+        let num_teams = self.players.iter().filter_map(|p| p.team).max();
+        (1..=num_teams.unwrap_or(0))
+            .map(|team| {
+                self.players
+                    .iter()
+                    .filter(|p| p.team == Some(team))
+                    .map(|p| p.id)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Remove a player from the game.
+    /// Its planets become neutral, keeping their ships, and its expeditions disappear.
+    pub fn forfeit(&mut self, player_id: usize) {
+        let owner = Some(player_id - 1);
+        for fleet in self.planets.iter_mut().flat_map(|p| p.fleets.iter_mut()) {
+            if fleet.owner == owner {
+                fleet.owner = None;
+            }
+        }
+        self.expeditions.retain(|e| e.fleet.owner != owner);
+        self.players[player_id - 1].alive = false;
     }
 
     pub fn living_players(&self) -> Vec<usize> {
@@ -139,6 +297,23 @@ impl PwState {
     }
 }
 
+/// Player numbers start at 1, while owners are player indexes
+fn player_num(owner: usize) -> usize {
+    owner + 1
+}
+
+fn battle_participant(fleet: &Fleet, survivor: Option<&Fleet>) -> BattleParticipant {
+    let remaining = match survivor {
+        Some(survivor) if survivor.owner == fleet.owner => survivor.ship_count,
+        _ => 0,
+    };
+    BattleParticipant {
+        owner: fleet.owner.map(player_num),
+        ship_count: fleet.ship_count,
+        losses: fleet.ship_count.saturating_sub(remaining),
+    }
+}
+
 impl Planet {
     pub fn owner(&self) -> Option<usize> {
         self.fleets.first().and_then(|f| f.owner)
@@ -161,39 +336,38 @@ impl Planet {
         self.fleets.push(fleet);
     }
 
-    fn resolve_combat(&mut self) {
-        // The player owning the largest fleet present will win the combat.
-        // Here, we resolve how many ships he will have left.
-        // note: in the current implementation, we could resolve by doing
-        // winner.ship_count -= second_largest.ship_count, but this does not
-        // allow for simple customizations (such as changing combat balance).
-
-        self.fleets
-            .sort_by(|a, b| a.ship_count.cmp(&b.ship_count).reverse());
-        while self.fleets.len() > 1 {
-            let fleet = self.fleets.pop().unwrap();
-            // destroy some ships
-            for other in self.fleets.iter_mut() {
-                other.ship_count -= fleet.ship_count;
-            }
-
-            // remove dead fleets
-            while self.fleets.last().map(|f| f.ship_count) == Some(0) {
-                self.fleets.pop();
+    /// Merge the fleets of allied players, who do not fight each other.
+    /// The ships join the first of their fleets, which is the defending fleet
+    /// when the planet is defended, and the first to arrive otherwise.
+    /// `sides` holds the side of every player, by owner.
+    fn merge_allies(&mut self, sides: &[Side]) {
+        let side = |fleet: &Fleet| fleet.owner.map(|owner| sides[owner]);
+        let mut merged: Vec<Fleet> = Vec::with_capacity(self.fleets.len());
+        for fleet in self.fleets.drain(..) {
+            match merged.iter_mut().find(|other| side(other) == side(&fleet)) {
+                Some(other) => other.ship_count += fleet.ship_count,
+                None => merged.push(fleet),
             }
         }
+        self.fleets = merged;
     }
 
-    fn distance(&self, other: &Planet) -> u64 {
-        let dx = self.x - other.x;
-        let dy = self.y - other.y;
-        (dx.powi(2) + dy.powi(2)).sqrt().ceil() as u64
+    /// Let the fleets orbiting this planet fight it out.
+    /// `defender` is the index of the fleet that was already stationed here.
+    fn resolve_combat(&mut self, rule: &dyn CombatRule, defender: Option<usize>) {
+        if self.fleets.len() < 2 {
+            // nobody to fight
+            return;
+        }
+        let fleets = mem::take(&mut self.fleets);
+        self.fleets.extend(rule.resolve(fleets, defender));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::combat::LargestFleetWins;
 
     fn get_test_planet() -> Planet {
         Planet {
@@ -202,6 +376,7 @@ mod tests {
             x: 0.0,
             y: 0.0,
             fleets: Vec::new(),
+            growth_rate: 1,
         }
     }
 
@@ -218,7 +393,7 @@ mod tests {
             ship_count: 2,
         });
 
-        p.resolve_combat();
+        p.resolve_combat(&LargestFleetWins, None);
 
         assert_eq!(p.fleets.len(), 1);
         assert_eq!(p.owner(), Some(0));
@@ -241,7 +416,7 @@ mod tests {
             ship_count: 12,
         });
 
-        p.resolve_combat();
+        p.resolve_combat(&LargestFleetWins, None);
 
         assert_eq!(p.fleets.len(), 1);
         assert_eq!(p.owner(), Some(2));
@@ -264,10 +439,162 @@ mod tests {
             ship_count: 10,
         });
 
-        p.resolve_combat();
+        p.resolve_combat(&LargestFleetWins, None);
 
         assert_eq!(p.fleets.len(), 0);
         assert_eq!(p.owner(), None);
         assert_eq!(p.ship_count(), 0);
     }
+
+    #[test]
+    fn test_planet_combat_allies() {
+        let mut p = get_test_planet();
+        p.orbit(Fleet {
+            owner: Some(0),
+            ship_count: 5,
+        });
+        p.orbit(Fleet {
+            owner: Some(1),
+            ship_count: 10,
+        });
+        p.orbit(Fleet {
+            owner: Some(2),
+            ship_count: 8,
+        });
+
+        // players 1 and 3 are allies, together outnumbering player 2
+        let sides = [Side::Team(1), Side::Team(2), Side::Team(1)];
+        p.merge_allies(&sides);
+        p.resolve_combat(&LargestFleetWins, Some(0));
+
+        assert_eq!(p.fleets.len(), 1);
+        assert_eq!(p.owner(), Some(0));
+        assert_eq!(p.ship_count(), 3);
+    }
+
+    #[test]
+    fn test_forfeit() {
+        let planet = |id: usize, owner: Option<usize>| Planet {
+            id,
+            name: id.to_string(),
+            x: id as f64,
+            y: 0.0,
+            fleets: vec![Fleet {
+                owner,
+                ship_count: 5,
+            }],
+            growth_rate: 1,
+        };
+        let mut state = PwState {
+            players: (1..=3)
+                .map(|id| Player {
+                    id,
+                    alive: true,
+                    team: None,
+                })
+                .collect(),
+            planets: vec![planet(0, Some(0)), planet(1, Some(1)), planet(2, Some(2))],
+            expeditions: vec![Expedition {
+                id: 0,
+                origin: 1,
+                target: 0,
+                fleet: Fleet {
+                    owner: Some(1),
+                    ship_count: 3,
+                },
+                turns_remaining: 5,
+            }],
+            expedition_num: 1,
+            turn_num: 0,
+            max_turns: 100,
+            combat_rule: Arc::new(LargestFleetWins),
+            variant: RulesVariant::default(),
+        };
+
+        state.forfeit(2);
+        assert_eq!(state.living_players(), vec![1, 3]);
+        assert_eq!(state.planets[1].owner(), None);
+        assert_eq!(state.planets[1].ship_count(), 5);
+        assert!(state.expeditions.is_empty());
+
+        // the player stays out of the game
+        state.step();
+        assert_eq!(state.living_players(), vec![1, 3]);
+        assert!(!state.is_finished());
+    }
+
+    #[test]
+    fn test_step_events() {
+        let planet = |id: usize, name: &str, owner: usize, ship_count: u64| Planet {
+            id,
+            name: name.to_string(),
+            x: id as f64,
+            y: 0.0,
+            fleets: vec![Fleet {
+                owner: Some(owner),
+                ship_count,
+            }],
+            growth_rate: 1,
+        };
+        let mut state = PwState {
+            players: (1..=2)
+                .map(|id| Player {
+                    id,
+                    alive: true,
+                    team: None,
+                })
+                .collect(),
+            planets: vec![planet(0, "a", 0, 5), planet(1, "b", 1, 2)],
+            expeditions: vec![Expedition {
+                id: 0,
+                origin: 0,
+                target: 1,
+                fleet: Fleet {
+                    owner: Some(0),
+                    ship_count: 4,
+                },
+                turns_remaining: 1,
+            }],
+            expedition_num: 1,
+            turn_num: 0,
+            max_turns: 100,
+            combat_rule: Arc::new(LargestFleetWins),
+            variant: RulesVariant::default(),
+        };
+
+        let events = state.step();
+        assert_eq!(
+            events,
+            vec![
+                TurnEvent::FleetArrived {
+                    expedition_id: 0,
+                    origin: "a".to_string(),
+                    destination: "b".to_string(),
+                    owner: Some(1),
+                    ship_count: 4,
+                },
+                TurnEvent::Battle {
+                    planet: "b".to_string(),
+                    participants: vec![
+                        BattleParticipant {
+                            owner: Some(2),
+                            ship_count: 2,
+                            losses: 2,
+                        },
+                        BattleParticipant {
+                            owner: Some(1),
+                            ship_count: 4,
+                            losses: 2,
+                        },
+                    ],
+                },
+                TurnEvent::PlanetCaptured {
+                    planet: "b".to_string(),
+                    previous_owner: Some(2),
+                    new_owner: Some(1),
+                },
+                TurnEvent::PlayerEliminated { player: 2 },
+            ]
+        );
+    }
 }
@@ -0,0 +1,34 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::config::MapError;
+use super::protocol as proto;
+use super::variant::RulesVariant;
+
+/// A game in progress to start a match from,
+/// such as a puzzle or a position from a regression suite.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Players that do not own anything in the state are eliminated from the start
+    pub num_players: usize,
+    #[serde(default)]
+    pub turn_num: u64,
+    /// The rules the scenario is played by, when they differ from the standard rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<RulesVariant>,
+    /// The state of the game, in which player 1 is the first player
+    #[serde(flatten)]
+    pub state: proto::State,
+}
+
+impl Scenario {
+    /// Read a scenario from a file. The scenario is not validated.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, MapError> {
+        let mut file = File::open(path)?;
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+        let scenario = serde_json::from_str(&buf)?;
+        Ok(scenario)
+    }
+}
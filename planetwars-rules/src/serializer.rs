@@ -39,6 +39,12 @@ impl<'a> Serializer<'a> {
                 .iter()
                 .map(|exp| self.serialize_expedition(exp))
                 .collect(),
+            teams: self
+                .state
+                .teams()
+                .iter()
+                .map(|team| team.iter().map(|&id| self.player_num(id - 1)).collect())
+                .collect(),
         }
     }
 
@@ -59,6 +65,8 @@ impl<'a> Serializer<'a> {
             y: planet.y,
             owner: planet.owner().map(|id| self.player_num(id)),
             ship_count: planet.ship_count(),
+            growth_rate: planet.growth_rate,
+            hidden: false,
         }
     }
 
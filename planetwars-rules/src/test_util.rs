@@ -0,0 +1,29 @@
+//! Helpers for building game states in tests.
+
+use super::protocol as proto;
+
+/// A planet on the x axis, holding 5 ships and growing 1 ship every turn
+pub fn test_planet(name: &str, x: f64, owner: Option<usize>) -> proto::Planet {
+    proto::Planet {
+        name: name.to_string(),
+        x,
+        y: 0.0,
+        owner,
+        ship_count: 5,
+        growth_rate: 1,
+        hidden: false,
+    }
+}
+
+/// A state with the given `(name, x, owner)` planets, built by `test_planet`,
+/// without expeditions or teams
+pub fn test_state(planets: &[(&str, f64, Option<usize>)]) -> proto::State {
+    proto::State {
+        planets: planets
+            .iter()
+            .map(|&(name, x, owner)| test_planet(name, x, owner))
+            .collect(),
+        expeditions: Vec::new(),
+        teams: Vec::new(),
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+
+use super::rules::Planet;
+
+/// The most recent version of the rules variant format.
+///
+/// Version 1 covers neutral production, planet capacity, the distance metric,
+/// fleet speed and the handling of dispatches over distance 0.
+pub const RULES_VARIANT_VERSION: u32 = 1;
+
+/// Tweaks to the standard game rules.
+/// The default variant plays by the standard rules.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RulesVariant {
+    #[serde(default = "current_version")]
+    pub version: u32,
+    /// Neutral planets that hold ships produce ships as well
+    #[serde(default)]
+    pub neutral_production: bool,
+    /// Planets stop producing ships once they hold this many.
+    /// Arriving fleets can still exceed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub planet_capacity: Option<u64>,
+    #[serde(default)]
+    pub distance_metric: DistanceMetric,
+    /// Distance a fleet covers every turn
+    #[serde(default = "default_fleet_speed")]
+    pub fleet_speed: f64,
+    #[serde(default)]
+    pub zero_distance: ZeroDistance,
+}
+
+fn current_version() -> u32 {
+    RULES_VARIANT_VERSION
+}
+
+fn default_fleet_speed() -> f64 {
+    1.0
+}
+
+impl Default for RulesVariant {
+    fn default() -> Self {
+        RulesVariant {
+            version: RULES_VARIANT_VERSION,
+            neutral_production: false,
+            planet_capacity: None,
+            distance_metric: DistanceMetric::default(),
+            fleet_speed: default_fleet_speed(),
+            zero_distance: ZeroDistance::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    #[default]
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+/// What happens to fleets sent to a planet at distance 0,
+/// such as the origin planet itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroDistance {
+    /// The fleet arrives when the turn is resolved,
+    /// like a fleet sent to a planet at distance 1.
+    #[default]
+    SameTurn,
+    /// The dispatch is rejected as an invalid command.
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RulesVariantError {
+    UnsupportedVersion(u32),
+    /// Fleet speed should be a positive, finite number
+    InvalidFleetSpeed(f64),
+}
+
+impl fmt::Display for RulesVariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RulesVariantError::UnsupportedVersion(version) => {
+                write!(f, "unsupported rules variant version {}", version)
+            }
+            RulesVariantError::InvalidFleetSpeed(speed) => {
+                write!(f, "invalid fleet speed {}", speed)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RulesVariantError {}
+
+impl RulesVariant {
+    pub fn validate(&self) -> Result<(), RulesVariantError> {
+        if self.version > RULES_VARIANT_VERSION {
+            return Err(RulesVariantError::UnsupportedVersion(self.version));
+        }
+        if !self.fleet_speed.is_finite() || self.fleet_speed <= 0.0 {
+            return Err(RulesVariantError::InvalidFleetSpeed(self.fleet_speed));
+        }
+        Ok(())
+    }
+
+    /// The amount of turns it takes a fleet to travel between the given planets
+    pub fn travel_turns(&self, origin: &Planet, target: &Planet) -> u64 {
+        self.travel_turns_between((origin.x, origin.y), (target.x, target.y))
+    }
+
+    /// The amount of turns it takes a fleet to travel between the given positions
+    pub fn travel_turns_between(&self, origin: (f64, f64), target: (f64, f64)) -> u64 {
+        let dx = (origin.0 - target.0).abs();
+        let dy = (origin.1 - target.1).abs();
+        let distance = match self.distance_metric {
+            DistanceMetric::Euclidean => (dx.powi(2) + dy.powi(2)).sqrt(),
+            DistanceMetric::Manhattan => dx + dy,
+            DistanceMetric::Chebyshev => dx.max(dy),
+        };
+        (distance / self.fleet_speed).ceil() as u64
+    }
+
+    /// The amount of ships a planet holding `ship_count` ships produces this turn
+    pub fn production(&self, ship_count: u64, growth_rate: u64) -> u64 {
+        match self.planet_capacity {
+            Some(capacity) => growth_rate.min(capacity.saturating_sub(ship_count)),
+            None => growth_rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planet_at(x: f64, y: f64) -> Planet {
+        Planet {
+            id: 0,
+            name: "p".to_string(),
+            fleets: Vec::new(),
+            x,
+            y,
+            growth_rate: 1,
+        }
+    }
+
+    #[test]
+    fn test_travel_turns() {
+        let (a, b) = (planet_at(0.0, 0.0), planet_at(3.0, 4.0));
+        let mut variant = RulesVariant::default();
+        assert_eq!(variant.travel_turns(&a, &b), 5);
+
+        variant.distance_metric = DistanceMetric::Manhattan;
+        assert_eq!(variant.travel_turns(&a, &b), 7);
+        variant.distance_metric = DistanceMetric::Chebyshev;
+        assert_eq!(variant.travel_turns(&a, &b), 4);
+
+        variant.fleet_speed = 3.0;
+        assert_eq!(variant.travel_turns(&a, &b), 2);
+    }
+
+    #[test]
+    fn test_parse_variant() {
+        let variant: RulesVariant =
+            serde_json::from_str(r#"{ "planet_capacity": 100, "zero_distance": "reject" }"#)
+                .unwrap();
+        assert_eq!(variant.version, RULES_VARIANT_VERSION);
+        assert_eq!(variant.planet_capacity, Some(100));
+        assert_eq!(variant.zero_distance, ZeroDistance::Reject);
+        assert_eq!(variant.fleet_speed, 1.0);
+        assert_eq!(variant.production(98, 5), 2);
+        assert_eq!(variant.production(120, 5), 0);
+
+        let variant = RulesVariant {
+            version: RULES_VARIANT_VERSION + 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            variant.validate(),
+            Err(RulesVariantError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use super::deserializer::{self, DeserializeError};
+use super::protocol as proto;
+use super::serializer;
+use super::variant::RulesVariant;
+
+/// Limits what players can see of the game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FogOfWar {
+    /// Players can only see planets and expeditions within this distance
+    /// of a planet they own.
+    pub vision_radius: f64,
+}
+
+impl FogOfWar {
+    /// Hide everything player 1 cannot see in the given state.
+    /// Hidden planets are blanked out, hidden expeditions are removed.
+    /// Players share their vision with their teammates.
+    /// Expeditions are placed along their way using the travel time of the given variant.
+    pub fn apply(&self, state: &mut proto::State, variant: &RulesVariant) {
+        let allies: Vec<usize> = state
+            .teams
+            .iter()
+            .find(|team| team.contains(&1))
+            .cloned()
+            .unwrap_or_else(|| vec![1]);
+        let is_allied = |owner: usize| allies.contains(&owner);
+        let positions: HashMap<&str, (f64, f64)> = state
+            .planets
+            .iter()
+            .map(|p| (p.name.as_str(), (p.x, p.y)))
+            .collect();
+        let vision_sources: Vec<(f64, f64)> = state
+            .planets
+            .iter()
+            .filter(|p| p.owner.is_some_and(is_allied))
+            .map(|p| (p.x, p.y))
+            .collect();
+        let is_visible = |pos: (f64, f64)| {
+            vision_sources
+                .iter()
+                .any(|&source| distance(source, pos) <= self.vision_radius)
+        };
+
+        let expeditions = std::mem::take(&mut state.expeditions);
+        state.expeditions = expeditions
+            .into_iter()
+            .filter(|exp| {
+                is_allied(exp.owner) || is_visible(expedition_position(exp, &positions, variant))
+            })
+            .collect();
+
+        for planet in state.planets.iter_mut() {
+            if !planet.owner.is_some_and(is_allied) && !is_visible((planet.x, planet.y)) {
+                planet.owner = None;
+                planet.ship_count = 0;
+                planet.hidden = true;
+            }
+        }
+    }
+}
+
+/// Reconstruct what the given player saw of a state, such as one from a match log.
+/// The returned state is rotated so that the given player is player 1,
+/// as it would have been sent to that player.
+pub fn player_view(
+    state: &proto::State,
+    player_num: usize,
+    num_players: usize,
+    fog_of_war: Option<&FogOfWar>,
+    variant: &RulesVariant,
+) -> Result<proto::State, DeserializeError> {
+    let pw_state = deserializer::deserialize(state, num_players, 0)?;
+    let mut view = serializer::serialize_rotated(&pw_state, player_num - 1);
+    if let Some(fog_of_war) = fog_of_war {
+        fog_of_war.apply(&mut view, variant);
+    }
+    Ok(view)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Expeditions travel in a straight line from their origin to their destination,
+/// covering an equal part of the way every turn.
+fn expedition_position(
+    exp: &proto::Expedition,
+    positions: &HashMap<&str, (f64, f64)>,
+    variant: &RulesVariant,
+) -> (f64, f64) {
+    let origin = positions[exp.origin.as_str()];
+    let destination = positions[exp.destination.as_str()];
+    // the travel time the expedition was dispatched with
+    let total_turns = variant.travel_turns_between(origin, destination);
+    if total_turns == 0 {
+        return destination;
+    }
+    let remaining = (exp.turns_remaining as f64 / total_turns as f64).min(1.0);
+    (
+        destination.0 + (origin.0 - destination.0) * remaining,
+        destination.1 + (origin.1 - destination.1) * remaining,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_planet;
+
+    fn expedition(id: u64, owner: usize, turns_remaining: u64) -> proto::Expedition {
+        proto::Expedition {
+            id,
+            ship_count: 3,
+            origin: "far".to_string(),
+            destination: "home".to_string(),
+            owner,
+            turns_remaining,
+        }
+    }
+
+    fn test_state() -> proto::State {
+        proto::State {
+            planets: vec![
+                test_planet("home", 0.0, Some(1)),
+                test_planet("near", 2.0, None),
+                test_planet("far", 10.0, Some(2)),
+            ],
+            expeditions: vec![expedition(0, 2, 9), expedition(1, 2, 2)],
+            teams: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fog_of_war() {
+        let mut state = test_state();
+        FogOfWar { vision_radius: 3.0 }.apply(&mut state, &RulesVariant::default());
+
+        assert!(!state.planets[0].hidden);
+        assert!(!state.planets[1].hidden);
+        assert_eq!(state.planets[1].ship_count, 5);
+
+        assert!(state.planets[2].hidden);
+        assert_eq!(state.planets[2].owner, None);
+        assert_eq!(state.planets[2].ship_count, 0);
+
+        // only the expedition that is about to arrive can be seen
+        assert_eq!(state.expeditions.len(), 1);
+        assert_eq!(state.expeditions[0].id, 1);
+    }
+
+    #[test]
+    fn test_fog_of_war_fleet_speed() {
+        let mut state = test_state();
+        let variant = RulesVariant {
+            fleet_speed: 2.0,
+            ..RulesVariant::default()
+        };
+        FogOfWar { vision_radius: 3.0 }.apply(&mut state, &variant);
+
+        // fast expeditions cover more distance every turn,
+        // so the expedition two turns away is still out of sight
+        assert!(state.expeditions.is_empty());
+    }
+
+    #[test]
+    fn test_player_view() {
+        let state = test_state();
+        let fog = FogOfWar { vision_radius: 3.0 };
+        let view = player_view(&state, 2, 2, Some(&fog), &RulesVariant::default()).unwrap();
+
+        // player 2 is rotated to be player 1, and sees its own expeditions.
+        assert_eq!(view.planets[2].owner, Some(1));
+        assert!(view.planets[0].hidden);
+        assert!(view.planets[1].hidden);
+        assert_eq!(view.expeditions.len(), 2);
+    }
+}
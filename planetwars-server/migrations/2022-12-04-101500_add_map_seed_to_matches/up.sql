@@ -0,0 +1 @@
+ALTER TABLE matches ADD COLUMN map_seed BIGINT;
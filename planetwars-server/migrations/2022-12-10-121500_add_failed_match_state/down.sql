@@ -0,0 +1,6 @@
+-- postgres cannot drop enum values, so the type has to be recreated
+UPDATE matches SET state = 'finished' WHERE state = 'failed';
+ALTER TYPE match_state RENAME TO match_state_old;
+CREATE TYPE match_state AS ENUM ('playing', 'finished');
+ALTER TABLE matches ALTER COLUMN state TYPE match_state USING state::text::match_state;
+DROP TYPE match_state_old;
@@ -0,0 +1 @@
+ALTER TYPE match_state ADD VALUE 'failed';
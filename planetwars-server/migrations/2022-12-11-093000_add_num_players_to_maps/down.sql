@@ -0,0 +1 @@
+ALTER TABLE maps DROP COLUMN num_players;
@@ -0,0 +1 @@
+ALTER TABLE maps ADD COLUMN num_players INTEGER NOT NULL DEFAULT 2;
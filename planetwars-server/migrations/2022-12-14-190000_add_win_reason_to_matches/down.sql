@@ -0,0 +1,3 @@
+ALTER TABLE matches DROP COLUMN num_turns;
+ALTER TABLE matches DROP COLUMN win_reason;
+DROP TYPE win_reason;
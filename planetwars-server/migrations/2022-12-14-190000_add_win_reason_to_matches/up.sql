@@ -0,0 +1,3 @@
+CREATE TYPE win_reason AS ENUM ('elimination', 'ships', 'planets', 'production');
+ALTER TABLE matches ADD COLUMN win_reason win_reason;
+ALTER TABLE matches ADD COLUMN num_turns INTEGER;
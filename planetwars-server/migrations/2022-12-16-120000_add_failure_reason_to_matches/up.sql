@@ -0,0 +1 @@
+ALTER TABLE matches ADD COLUMN failure_reason TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE bots DROP COLUMN protocol;
+DROP TYPE bot_protocol;
@@ -0,0 +1,2 @@
+CREATE TYPE bot_protocol AS ENUM ('v1', 'v2', 'v3');
+ALTER TABLE bots ADD COLUMN protocol bot_protocol NOT NULL DEFAULT 'v1';
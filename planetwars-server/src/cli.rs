@@ -1,9 +1,14 @@
 extern crate planetwars_server;
 extern crate tokio;
 
+use std::path::{Path, PathBuf};
+
 use clap::Parser;
+use diesel::{OptionalExtension, PgConnection};
+use planetwars_rules::classic::parse_classic_map;
 use planetwars_server::db;
-use planetwars_server::{create_db_pool, get_config};
+use planetwars_server::modules::maps::{check_map, check_map_name, save_map};
+use planetwars_server::{create_db_pool, get_config, GlobalConfig};
 
 #[derive(clap::Parser)]
 struct Args {
@@ -14,12 +19,14 @@ struct Args {
 #[derive(clap::Subcommand)]
 enum Action {
     SetPassword(SetPassword),
+    ImportClassicMaps(ImportClassicMaps),
 }
 
 impl Action {
     async fn run(self) {
         match self {
             Action::SetPassword(set_password) => set_password.run().await,
+            Action::ImportClassicMaps(import) => import.run().await,
         }
     }
 }
@@ -47,6 +54,55 @@ impl SetPassword {
     }
 }
 
+/// Import maps in the text format of the original AI Challenge.
+/// Maps are named after their file name.
+#[derive(clap::Parser)]
+struct ImportClassicMaps {
+    #[clap(value_parser, required = true)]
+    map_files: Vec<PathBuf>,
+}
+
+impl ImportClassicMaps {
+    async fn run(self) {
+        let global_config = get_config().unwrap();
+        let pool = create_db_pool(&global_config).await;
+        let mut conn = pool.get().await.expect("could not get database connection");
+
+        for path in self.map_files {
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .expect("invalid map file name")
+                .to_lowercase();
+            if let Err(err) = import_classic_map(&path, &name, &mut conn, &global_config) {
+                eprintln!("skipping {}: {}", path.display(), err);
+            } else {
+                println!("imported {} as {}", path.display(), name);
+            }
+        }
+    }
+}
+
+fn import_classic_map(
+    path: &Path,
+    name: &str,
+    conn: &mut PgConnection,
+    config: &GlobalConfig,
+) -> Result<(), String> {
+    check_map_name(name)?;
+    let existing_map = db::maps::find_map_by_name(name, conn)
+        .optional()
+        .map_err(|err| err.to_string())?;
+    if existing_map.is_some() {
+        return Err("a map with this name already exists".to_string());
+    }
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let map = parse_classic_map(&text).map_err(|err| err.to_string())?;
+    let num_players = check_map(&map)?;
+    save_map(name, &map, num_players, conn, config).map_err(|err| err.to_string())?;
+    Ok(())
+}
+
 #[tokio::main]
 pub async fn main() {
     let args = Args::parse();
@@ -1,6 +1,7 @@
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
+pub use crate::db_types::BotProtocol;
 use crate::schema::{bot_versions, bots};
 use chrono;
 
@@ -9,6 +10,7 @@ use chrono;
 pub struct NewBot<'a> {
     pub owner_id: Option<i32>,
     pub name: &'a str,
+    pub protocol: BotProtocol,
 }
 
 #[derive(Queryable, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -17,6 +19,8 @@ pub struct Bot {
     pub owner_id: Option<i32>,
     pub name: String,
     pub active_version: Option<i32>,
+    /// the protocol the bot speaks
+    pub protocol: BotProtocol,
 }
 
 pub fn create_bot(new_bot: &NewBot, conn: &mut PgConnection) -> QueryResult<Bot> {
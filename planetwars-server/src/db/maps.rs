@@ -7,6 +7,7 @@ use crate::schema::maps;
 pub struct NewMap<'a> {
     pub name: &'a str,
     pub file_path: &'a str,
+    pub num_players: i32,
 }
 
 #[derive(Queryable, Clone, Debug)]
@@ -15,6 +16,7 @@ pub struct Map {
     pub name: String,
     pub file_path: String,
     pub is_ranked: bool,
+    pub num_players: i32,
 }
 
 pub fn create_map(new_map: NewMap, conn: &mut PgConnection) -> QueryResult<Map> {
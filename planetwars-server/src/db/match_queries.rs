@@ -24,7 +24,8 @@ impl QueryFragment<Pg> for ListBotMatches {
     fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
         out.unsafe_to_cache_prepared();
 
-        out.push_sql("SELECT matches.* FROM matches");
+        // a bot can occur multiple times in a free-for-all match
+        out.push_sql("SELECT DISTINCT matches.* FROM matches");
         out.push_sql(" JOIN (");
         out.push_sql(concat!(
             "SELECT match_id, player_id, bot_version_id, bot_id ",
@@ -41,7 +42,6 @@ impl QueryFragment<Pg> for ListBotMatches {
 
         out.push_sql(") main_player ON matches.id = main_player.match_id");
 
-        // TODO: this won't work properly for matches with > 2 players
         if let Some(opponent_id) = self.opponent_id.as_ref() {
             out.push_sql(" JOIN (");
             out.push_sql(concat!(
@@ -52,7 +52,10 @@ impl QueryFragment<Pg> for ListBotMatches {
             ));
             out.push_bind_param::<Integer, _>(opponent_id)?;
 
-            out.push_sql(") other_player ON matches.id = other_player.match_id");
+            out.push_sql(concat!(
+                ") other_player ON matches.id = other_player.match_id",
+                " AND other_player.player_id <> main_player.player_id"
+            ));
         }
 
         out.push_sql(" WHERE matches.state = 'finished' AND matches.is_public = true");
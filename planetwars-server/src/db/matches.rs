@@ -1,4 +1,4 @@
-pub use crate::db_types::MatchState;
+pub use crate::db_types::{MatchState, WinReason};
 use chrono::NaiveDateTime;
 use diesel::associations::BelongsTo;
 use diesel::pg::Pg;
@@ -23,6 +23,8 @@ pub struct NewMatch<'a> {
     pub log_path: &'a str,
     pub is_public: bool,
     pub map_id: Option<i32>,
+    /// seed of the generated map this match is played on, if any
+    pub map_seed: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -46,6 +48,11 @@ pub struct MatchBase {
     pub winner: Option<i32>,
     pub is_public: bool,
     pub map_id: Option<i32>,
+    pub map_seed: Option<i64>,
+    pub win_reason: Option<WinReason>,
+    pub num_turns: Option<i32>,
+    /// why the match could not be played, for failed matches
+    pub failure_reason: Option<String>,
 }
 
 #[derive(Queryable, Identifiable, Associations, Clone)]
@@ -143,9 +150,14 @@ fn fetch_full_match_data(
 pub fn fetch_ranked_maps(amount: i64, conn: &mut PgConnection) -> QueryResult<Vec<FullMatchData>> {
     conn.transaction(|conn| {
         let matches = matches::table
-            .inner_join(maps::table)
+            .left_join(maps::table)
             .filter(matches::state.eq(MatchState::Finished))
-            .filter(maps::is_ranked.eq(true))
+            .filter(
+                maps::is_ranked
+                    .nullable()
+                    .eq(true)
+                    .or(matches::map_seed.is_not_null()),
+            )
             .order_by(matches::created_at.desc())
             .limit(amount)
             .select(matches::all_columns)
@@ -287,16 +299,39 @@ pub fn find_match_base(id: i32, conn: &mut PgConnection) -> QueryResult<MatchBas
 }
 
 pub enum MatchResult {
-    Finished { winner: Option<i32> },
+    Finished {
+        winner: Option<i32>,
+        win_reason: Option<WinReason>,
+        num_turns: i32,
+    },
+    Failed {
+        reason: String,
+    },
 }
 
 pub fn save_match_result(id: i32, result: MatchResult, conn: &mut PgConnection) -> QueryResult<()> {
-    let MatchResult::Finished { winner } = result;
+    let (state, winner, win_reason, num_turns, failure_reason) = match result {
+        MatchResult::Finished {
+            winner,
+            win_reason,
+            num_turns,
+        } => (
+            MatchState::Finished,
+            winner,
+            win_reason,
+            Some(num_turns),
+            None,
+        ),
+        MatchResult::Failed { reason } => (MatchState::Failed, None, None, None, Some(reason)),
+    };
 
     diesel::update(matches::table.find(id))
         .set((
             matches::winner.eq(winner),
-            matches::state.eq(MatchState::Finished),
+            matches::state.eq(state),
+            matches::win_reason.eq(win_reason),
+            matches::num_turns.eq(num_turns),
+            matches::failure_reason.eq(failure_reason),
         ))
         .execute(conn)?;
     Ok(())
@@ -332,6 +367,9 @@ pub struct BotStatsRecord {
     pub count: i64,
 }
 
+/// Match results of a bot per opponent and map.
+/// In free-for-all matches, every opponent is counted separately;
+/// a match won by a third player counts as a tie against that opponent.
 pub fn fetch_bot_stats(
     bot_name: &str,
     db_conn: &mut PgConnection,
@@ -343,7 +381,10 @@ FROM (
     SELECT
         opponent_bot.name as opponent,
         maps.name as map,
-        (matches.winner = bot_player.player_id) as win
+        CASE
+            WHEN matches.winner = bot_player.player_id THEN TRUE
+            WHEN matches.winner = opponent_player.player_id THEN FALSE
+        END as win
     FROM matches
     JOIN maps
         ON matches.map_id = maps.id
@@ -355,7 +396,7 @@ FROM (
         ON bot.id = bot_version.bot_id
     JOIN match_players opponent_player
         ON opponent_player.match_id = matches.id
-        AND opponent_player.player_id = 1 - bot_player.player_id
+        AND opponent_player.player_id <> bot_player.player_id
     JOIN bot_versions opponent_version
         ON opponent_version.id = opponent_player.bot_version_id
     LEFT OUTER JOIN bots opponent_bot
@@ -1,4 +1,5 @@
 use diesel_derive_enum::DbEnum;
+use planetwars_rules::protocol::ProtocolVersion;
 use serde::{Deserialize, Serialize};
 
 #[derive(DbEnum, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -7,4 +8,42 @@ use serde::{Deserialize, Serialize};
 pub enum MatchState {
     Playing,
     Finished,
+    /// The match could not be played, eg. because its map is invalid
+    Failed,
+}
+
+/// Why the winner of a match won
+#[derive(DbEnum, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[DieselTypePath = "crate::schema::sql_types::WinReason"]
+#[serde(rename_all = "snake_case")]
+pub enum WinReason {
+    /// All other players were eliminated
+    Elimination,
+    /// Won the ships tiebreak at the turn limit
+    Ships,
+    /// Won the planets tiebreak at the turn limit
+    Planets,
+    /// Won the production tiebreak at the turn limit
+    Production,
+}
+
+/// The protocol a bot speaks, see `ProtocolVersion`
+#[derive(DbEnum, Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[DieselTypePath = "crate::schema::sql_types::BotProtocol"]
+#[serde(rename_all = "snake_case")]
+pub enum BotProtocol {
+    #[default]
+    V1,
+    V2,
+    V3,
+}
+
+impl From<BotProtocol> for ProtocolVersion {
+    fn from(protocol: BotProtocol) -> Self {
+        match protocol {
+            BotProtocol::V1 => ProtocolVersion::V1,
+            BotProtocol::V2 => ProtocolVersion::V2,
+            BotProtocol::V3 => ProtocolVersion::V3,
+        }
+    }
 }
@@ -18,8 +18,10 @@ use bb8_diesel::{self, DieselConnectionManager};
 use config::ConfigError;
 use diesel::{Connection, PgConnection};
 use modules::client_api::run_client_api;
+use modules::matches::MatchLimitsConfig;
 use modules::ranking::run_ranker;
 use modules::registry::registry_service;
+use planetwars_rules::Tiebreak;
 use serde::{Deserialize, Serialize};
 
 use axum::{
@@ -65,6 +67,16 @@ pub struct GlobalConfig {
 
     /// Whether to run the ranker
     pub ranker_enabled: bool,
+    /// Whether the ranker should also play matches on generated maps
+    #[serde(default)]
+    pub ranker_generated_maps: bool,
+    /// How to decide the winner of matches that reach the turn limit.
+    /// When empty, such matches are a draw.
+    #[serde(default)]
+    pub tiebreaks: Vec<Tiebreak>,
+    /// Turn limits and timeouts, by match type and map
+    #[serde(default)]
+    pub match_limits: MatchLimitsConfig,
 }
 
 // TODO: do we still need this? Is there a better way?
@@ -74,11 +86,12 @@ pub async fn seed_simplebot(config: &GlobalConfig, pool: &ConnectionPool) {
     let mut conn = pool.get().await.expect("could not get database connection");
     // This transaction is expected to fail when simplebot already exists.
     let _res = conn.transaction::<(), diesel::result::Error, _>(|conn| {
-        use db::bots::NewBot;
+        use db::bots::{BotProtocol, NewBot};
 
         let new_bot = NewBot {
             name: "simplebot",
             owner_id: None,
+            protocol: BotProtocol::V1,
         };
 
         let simplebot = db::bots::create_bot(&new_bot, conn)?;
@@ -105,6 +118,9 @@ pub async fn create_db_pool(config: &GlobalConfig) -> DbPool {
 fn init_directories(config: &GlobalConfig) -> std::io::Result<()> {
     fs::create_dir_all(&config.bots_directory)?;
     fs::create_dir_all(&config.maps_directory)?;
+    fs::create_dir_all(
+        PathBuf::from(&config.maps_directory).join(modules::matches::GENERATED_MAPS_DIRECTORY),
+    )?;
     fs::create_dir_all(&config.match_logs_directory)?;
 
     let registry_path = PathBuf::from(&config.registry_directory);
@@ -26,7 +26,7 @@ use crate::util::gen_alphanumeric;
 use crate::ConnectionPool;
 use crate::GlobalConfig;
 
-use super::matches::{MatchPlayer, RunMatch};
+use super::matches::{MatchMap, MatchPlayer, MatchType, RunMatch};
 
 pub struct ClientApiServer {
     conn_pool: ConnectionPool,
@@ -153,9 +153,18 @@ impl pb::client_api_service_server::ClientApiService for ClientApiServer {
 
         let match_request = req.get_ref();
 
-        let (opponent_bot, opponent_bot_version) =
-            db::bots::find_bot_with_version_by_name(&match_request.opponent_name, &mut conn)
-                .map_err(|_| Status::not_found("opponent not found"))?;
+        let opponent_names = std::iter::once(&match_request.opponent_name)
+            .chain(match_request.extra_opponent_names.iter());
+        let mut opponents = Vec::new();
+        for opponent_name in opponent_names {
+            let (opponent_bot, opponent_bot_version) =
+                db::bots::find_bot_with_version_by_name(opponent_name, &mut conn)
+                    .map_err(|_| Status::not_found("opponent not found"))?;
+            opponents.push(MatchPlayer::BotVersion {
+                bot: Some(opponent_bot),
+                version: opponent_bot_version,
+            });
+        }
 
         let map_name = match match_request.map_name.as_str() {
             "" => "hex",
@@ -163,6 +172,12 @@ impl pb::client_api_service_server::ClientApiService for ClientApiServer {
         };
         let map = db::maps::find_map_by_name(map_name, &mut conn)
             .map_err(|_| Status::not_found("map not found"))?;
+        if opponents.len() + 1 != map.num_players as usize {
+            return Err(Status::invalid_argument(format!(
+                "map {} requires {} players",
+                map.name, map.num_players
+            )));
+        }
 
         let player_key = gen_alphanumeric(32);
         // ensure that the player key is registered in the router when we send a response
@@ -173,19 +188,15 @@ impl pb::client_api_service_server::ClientApiService for ClientApiServer {
             player_key: player_key.clone(),
             router: self.router.clone(),
         });
+        let mut players = vec![MatchPlayer::BotSpec {
+            spec: remote_bot_spec,
+        }];
+        players.extend(opponents);
         let run_match = RunMatch::new(
             self.runner_config.clone(),
-            false,
-            map,
-            vec![
-                MatchPlayer::BotSpec {
-                    spec: remote_bot_spec,
-                },
-                MatchPlayer::BotVersion {
-                    bot: Some(opponent_bot),
-                    version: opponent_bot_version,
-                },
-            ],
+            MatchType::Remote,
+            MatchMap::Stored(map),
+            players,
         );
         let (created_match, _) = run_match
             .run(self.conn_pool.clone())
@@ -216,7 +227,7 @@ impl runner::BotSpec for RemoteBotSpec {
         player_id: u32,
         event_bus: Arc<Mutex<EventBus>>,
         _match_logger: MatchLogger,
-    ) -> Box<dyn PlayerHandle> {
+    ) -> Result<Box<dyn PlayerHandle>, runner::BotStartError> {
         let (server_msg_snd, server_msg_recv) = mpsc::unbounded_channel();
 
         let client_messages_promise = {
@@ -225,7 +236,7 @@ impl runner::BotSpec for RemoteBotSpec {
             let mut routing_table = self.router.routing_table.lock().unwrap();
             let connection_state = routing_table
                 .remove(&self.player_key)
-                .expect("player key not found in routing table");
+                .ok_or(runner::BotStartError::NotConnected)?;
 
             match connection_state {
                 PlayerConnectionState::Reserved => {
@@ -252,33 +263,27 @@ impl runner::BotSpec for RemoteBotSpec {
 
         let client_messages_future =
             tokio::time::timeout(Duration::from_secs(10), client_messages_promise.get_value());
-
-        let join_handle = if let Ok(Ok(client_messages)) = client_messages_future.await {
-            tokio::spawn(handle_bot_messages(
-                player_id,
-                event_bus.clone(),
-                client_messages,
-            ))
-        } else {
-            // since we don't have a mechanism for bot failure yet,
-            // this hack will have to do for now.
-            // TODO: FIXME
-            tokio::spawn(futures::future::ready(()))
-        };
+        let client_messages = client_messages_future.await;
 
         // ensure router cleanup
         self.router.take(&self.player_key);
 
-        // If the player did not connect, the receiving half of `sender`
-        // will be dropped here, resulting in a time-out for every turn.
-        // This is fine for now, but
-        // TODO: provide a formal mechanism for player startup failure
-        Box::new(RemoteBotHandle {
+        let client_messages = match client_messages {
+            Ok(Ok(client_messages)) => client_messages,
+            _ => return Err(runner::BotStartError::NotConnected),
+        };
+        let join_handle = tokio::spawn(handle_bot_messages(
+            player_id,
+            event_bus.clone(),
+            client_messages,
+        ));
+
+        Ok(Box::new(RemoteBotHandle {
             sender: server_msg_snd,
             player_id,
             event_bus,
             join_handle,
-        })
+        }))
     }
 }
 
@@ -313,7 +318,9 @@ impl PlayerHandle for RemoteBotHandle {
     fn send_request(&mut self, r: RequestMessage) {
         let req = pb::PlayerActionRequest {
             action_request_id: r.request_id as i32,
-            content: r.content,
+            // read-only requests are only made by the legacy protocol adapter,
+            // which remote bots do not use
+            content: r.content.unwrap_or_default(),
         };
 
         let server_message = pb::PlayerApiServerMessage {
@@ -0,0 +1,82 @@
+use std::{collections::HashSet, fs::File, io, path::PathBuf};
+
+use diesel::{Connection, PgConnection};
+use planetwars_rules::config::Map as PlanetwarsMap;
+
+use crate::{db, GlobalConfig};
+
+const MIN_MAP_PLAYERS: usize = 2;
+const MAX_MAP_PLAYERS: usize = 6;
+
+/// Check whether a map is valid, returning the amount of players it is meant for.
+pub fn check_map(map: &PlanetwarsMap) -> Result<usize, String> {
+    let players: HashSet<usize> = map.planets.iter().filter_map(|p| p.owner).collect();
+    let num_players = players.len();
+
+    if num_players < MIN_MAP_PLAYERS || num_players > MAX_MAP_PLAYERS {
+        return Err(format!(
+            "maps should have between {} and {} players",
+            MIN_MAP_PLAYERS, MAX_MAP_PLAYERS
+        ));
+    }
+    // this also ensures that players are numbered 1 to num_players
+    map.validate(num_players).map_err(|err| err.to_string())?;
+
+    if map.num_players.map_or(false, |n| n != num_players) {
+        return Err("num_players does not match the players on the map".to_string());
+    }
+
+    Ok(num_players)
+}
+
+// TODO: remove duplication (bot name, user name)
+pub fn check_map_name(name: &str) -> Result<(), &str> {
+    if !name
+        .chars()
+        .all(|c| !c.is_uppercase() && (c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+    {
+        return Err("Only [a-z-_] are allowed in map names");
+    }
+
+    if name.len() < 3 || name.len() > 32 {
+        return Err("map name should be between 3 and 32 characters");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SaveMapError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+    #[error("could not write map file: {0}")]
+    WriteFailed(#[from] io::Error),
+}
+
+/// Write a checked map to the maps directory and register it in the database.
+pub fn save_map(
+    name: &str,
+    map: &PlanetwarsMap,
+    num_players: usize,
+    conn: &mut PgConnection,
+    config: &GlobalConfig,
+) -> Result<db::maps::Map, SaveMapError> {
+    let rel_map_path = format!("{}.json", name);
+
+    // the map is only registered once its file was written,
+    // and the file is only written when it could be registered
+    conn.transaction(|conn| {
+        let db_map = db::maps::create_map(
+            db::maps::NewMap {
+                name,
+                file_path: &rel_map_path,
+                num_players: num_players as i32,
+            },
+            conn,
+        )?;
+        let full_map_path = PathBuf::from(&config.maps_directory).join(&rel_map_path);
+        let file = File::create(full_map_path)?;
+        serde_json::to_writer_pretty(file, map).map_err(io::Error::from)?;
+        Ok(db_map)
+    })
+}
@@ -1,7 +1,11 @@
 use diesel::{Connection, PgConnection, QueryResult};
 use planetwars_matchrunner::{self as runner, docker_runner::DockerBotSpec, BotSpec, MatchConfig};
-use runner::MatchOutcome;
-use std::{path::PathBuf, sync::Arc};
+use planetwars_rules::generator::{generate_map, GenerateMapError, MapGenParams};
+use planetwars_rules::protocol::ProtocolVersion;
+use planetwars_rules::{CombatRuleConfig, MapSource};
+use runner::{MatchError, MatchOutcome, TimeBank, TimeControl};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, io, path::PathBuf, sync::Arc, time::Duration};
 use tokio::task::JoinHandle;
 
 use crate::{
@@ -14,15 +18,146 @@ use crate::{
     ConnectionPool, GlobalConfig,
 };
 
+/// Subdirectory of the maps directory where generated maps are written
+pub const GENERATED_MAPS_DIRECTORY: &str = "generated";
+
+/// Seeds are stored as a signed integer, so they cannot exceed this value.
+pub const MAX_MAP_SEED: u64 = i64::MAX as u64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunMatchError {
+    #[error("database error")]
+    DatabaseError(#[from] diesel::result::Error),
+    #[error("invalid map parameters: {0}")]
+    InvalidMapParams(GenerateMapError),
+    #[error("could not write generated map")]
+    MapWriteFailed(#[from] io::Error),
+}
+
 pub struct RunMatch {
     log_file_name: String,
     players: Vec<MatchPlayer>,
     config: Arc<GlobalConfig>,
-    is_public: bool,
-    // Map is mandatory for now.
-    // It would be nice to allow "anonymous" (eg. randomly generated) maps
-    // in the future, too.
-    map: Map,
+    match_type: MatchType,
+    map: MatchMap,
+}
+
+/// Why a match is played
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchType {
+    /// Played by the ranker; these matches are public
+    Ranked,
+    /// Played from the web editor
+    Demo,
+    /// Played by a bot connected through the client api
+    Remote,
+}
+
+/// Limits on the length of a match and the time bots get to respond.
+/// Unset limits fall back to the matchrunner defaults.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchLimits {
+    pub max_turns: Option<u64>,
+    pub turn_timeout_ms: Option<u64>,
+    /// Timeout for the first turn, for bots that take a while to start up
+    pub first_turn_timeout_ms: Option<u64>,
+    /// Extra time bots can spend over the turn timeout during a match
+    pub time_bank_ms: Option<u64>,
+    /// Time added to the time bank every turn
+    pub time_bank_increment_ms: Option<u64>,
+}
+
+impl MatchLimits {
+    /// Combine with more specific limits, which take precedence
+    fn overridden_by(&self, other: &MatchLimits) -> MatchLimits {
+        MatchLimits {
+            max_turns: other.max_turns.or(self.max_turns),
+            turn_timeout_ms: other.turn_timeout_ms.or(self.turn_timeout_ms),
+            first_turn_timeout_ms: other.first_turn_timeout_ms.or(self.first_turn_timeout_ms),
+            time_bank_ms: other.time_bank_ms.or(self.time_bank_ms),
+            time_bank_increment_ms: other.time_bank_increment_ms.or(self.time_bank_increment_ms),
+        }
+    }
+
+    pub fn max_turns(&self) -> u64 {
+        self.max_turns.unwrap_or(runner::DEFAULT_MAX_TURNS)
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        TimeControl {
+            turn_timeout: self
+                .turn_timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(runner::DEFAULT_TURN_TIMEOUT),
+            first_turn_timeout: self.first_turn_timeout_ms.map(Duration::from_millis),
+            time_bank: self.time_bank_ms.map(|time_bank_ms| TimeBank {
+                initial: Duration::from_millis(time_bank_ms),
+                increment: Duration::from_millis(self.time_bank_increment_ms.unwrap_or(0)),
+            }),
+        }
+    }
+}
+
+/// Match limits for every match type and map.
+/// Map limits take precedence over match type limits,
+/// which take precedence over the default limits.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MatchLimitsConfig {
+    #[serde(default)]
+    pub default: MatchLimits,
+    #[serde(default)]
+    pub ranked: MatchLimits,
+    #[serde(default)]
+    pub demo: MatchLimits,
+    #[serde(default)]
+    pub remote: MatchLimits,
+    /// Limits by map name
+    #[serde(default)]
+    pub maps: HashMap<String, MatchLimits>,
+}
+
+impl MatchLimitsConfig {
+    pub fn limits_for(&self, match_type: MatchType, map_name: &str) -> MatchLimits {
+        let type_limits = match match_type {
+            MatchType::Ranked => &self.ranked,
+            MatchType::Demo => &self.demo,
+            MatchType::Remote => &self.remote,
+        };
+        let limits = self.default.overridden_by(type_limits);
+        match self.maps.get(map_name) {
+            Some(map_limits) => limits.overridden_by(map_limits),
+            None => limits,
+        }
+    }
+}
+
+/// The map a match is played on
+#[derive(Clone, Debug)]
+pub enum MatchMap {
+    /// A map that was uploaded to the server
+    Stored(Map),
+    /// A map generated from the given seed, using the default generator parameters.
+    /// The seed should not exceed `MAX_MAP_SEED`.
+    Generated { seed: u64 },
+}
+
+impl MatchMap {
+    /// The amount of players a match on this map should have
+    pub fn num_players(&self) -> usize {
+        match self {
+            MatchMap::Stored(map) => map.num_players as usize,
+            MatchMap::Generated { seed } => generated_map_params(*seed).num_players,
+        }
+    }
+}
+
+/// Generated maps are stored by their seed only,
+/// so all other parameters should remain fixed.
+fn generated_map_params(seed: u64) -> MapGenParams {
+    MapGenParams {
+        seed,
+        ..Default::default()
+    }
 }
 
 pub enum MatchPlayer {
@@ -39,8 +174,8 @@ impl RunMatch {
     // TODO: create a MatchParams struct
     pub fn new(
         config: Arc<GlobalConfig>,
-        is_public: bool,
-        map: Map,
+        match_type: MatchType,
+        map: MatchMap,
         players: Vec<MatchPlayer>,
     ) -> Self {
         let log_file_name = format!("{}.log", gen_alphanumeric(16));
@@ -48,35 +183,73 @@ impl RunMatch {
             config,
             log_file_name,
             players,
-            is_public,
+            match_type,
             map,
         }
     }
 
-    fn into_runner_config(self) -> runner::MatchConfig {
+    /// Returns the name and file path of the match map,
+    /// writing it to disk first when it is generated.
+    fn prepare_map(&self) -> Result<(String, PathBuf), RunMatchError> {
+        match &self.map {
+            MatchMap::Stored(map) => Ok((
+                map.name.clone(),
+                PathBuf::from(&self.config.maps_directory).join(&map.file_path),
+            )),
+            MatchMap::Generated { seed } => Ok((
+                format!("generated-{}", seed),
+                write_generated_map(&self.config, *seed)?,
+            )),
+        }
+    }
+
+    fn into_runner_config(self, map_name: String, map_path: PathBuf) -> runner::MatchConfig {
+        let limits = self
+            .config
+            .match_limits
+            .limits_for(self.match_type, &map_name);
+
         runner::MatchConfig {
-            map_path: PathBuf::from(&self.config.maps_directory).join(self.map.file_path),
-            map_name: self.map.name,
+            map: MapSource::File(map_path),
+            map_name,
             log_path: PathBuf::from(&self.config.match_logs_directory).join(&self.log_file_name),
             players: self
                 .players
                 .into_iter()
-                .map(|player| runner::MatchPlayer {
-                    bot_spec: match player {
-                        MatchPlayer::BotVersion { bot, version } => {
-                            bot_version_to_botspec(&self.config, bot.as_ref(), &version)
-                        }
-                        MatchPlayer::BotSpec { spec } => spec,
+                .map(|player| match player {
+                    MatchPlayer::BotVersion { bot, version } => runner::MatchPlayer {
+                        bot_spec: bot_version_to_botspec(&self.config, bot.as_ref(), &version),
+                        protocol: bot
+                            .as_ref()
+                            .map_or(ProtocolVersion::default(), |bot| bot.protocol.into()),
+                        name: bot.map(|bot| bot.name),
+                        report_command_errors: false,
+                    },
+                    MatchPlayer::BotSpec { spec } => runner::MatchPlayer {
+                        bot_spec: spec,
+                        name: None,
+                        protocol: ProtocolVersion::default(),
+                        report_command_errors: false,
                     },
                 })
                 .collect(),
+            max_turns: limits.max_turns(),
+            time_control: limits.time_control(),
+            tiebreaks: self.config.tiebreaks.clone(),
+            // stored maps carry their own rules variant
+            rules_variant: None,
+            combat_rule: CombatRuleConfig::default(),
+            fog_of_war: None,
+            teams: Vec::new(),
         }
     }
 
     pub async fn run(
         self,
         conn_pool: ConnectionPool,
-    ) -> QueryResult<(MatchData, JoinHandle<MatchOutcome>)> {
+    ) -> Result<(MatchData, JoinHandle<Result<MatchOutcome, MatchError>>), RunMatchError> {
+        // write the map before creating the match, so that a failure leaves no trace
+        let (map_name, map_path) = self.prepare_map()?;
         let match_data = {
             // TODO: it would be nice to get an already-open connection here when possible.
             // Maybe we need an additional abstraction, bundling a connection and connection pool?
@@ -84,7 +257,7 @@ impl RunMatch {
             self.store_in_database(&mut db_conn)?
         };
 
-        let runner_config = self.into_runner_config();
+        let runner_config = self.into_runner_config(map_name, map_path);
         let handle = tokio::spawn(run_match_task(conn_pool, runner_config, match_data.base.id));
 
         Ok((match_data, handle))
@@ -94,8 +267,16 @@ impl RunMatch {
         let new_match_data = db::matches::NewMatch {
             state: db::matches::MatchState::Playing,
             log_path: &self.log_file_name,
-            is_public: self.is_public,
-            map_id: Some(self.map.id),
+            is_public: self.match_type == MatchType::Ranked,
+            map_id: match &self.map {
+                MatchMap::Stored(map) => Some(map.id),
+                MatchMap::Generated { .. } => None,
+            },
+            // seeds do not exceed MAX_MAP_SEED, so they fit in the signed column
+            map_seed: match self.map {
+                MatchMap::Stored(_) => None,
+                MatchMap::Generated { seed } => Some(seed as i64),
+            },
         };
         let new_match_players = self
             .players
@@ -112,6 +293,18 @@ impl RunMatch {
     }
 }
 
+/// Generate the map for given seed, and write it to the generated maps directory.
+/// Returns the path of the written map file.
+fn write_generated_map(config: &GlobalConfig, seed: u64) -> Result<PathBuf, RunMatchError> {
+    let map = generate_map(&generated_map_params(seed)).map_err(RunMatchError::InvalidMapParams)?;
+    let map_path = PathBuf::from(&config.maps_directory)
+        .join(GENERATED_MAPS_DIRECTORY)
+        .join(format!("{}.json", seed));
+    let file = File::create(&map_path)?;
+    serde_json::to_writer_pretty(file, &map).map_err(io::Error::from)?;
+    Ok(map_path)
+}
+
 pub fn bot_version_to_botspec(
     runner_config: &GlobalConfig,
     bot: Option<&db::bots::Bot>,
@@ -159,12 +352,22 @@ fn python_docker_bot_spec(config: &GlobalConfig, code_bundle_path: &str) -> Box<
     })
 }
 
+fn db_win_reason(win_reason: planetwars_rules::WinReason) -> db::matches::WinReason {
+    use planetwars_rules::{Tiebreak, WinReason};
+    match win_reason {
+        WinReason::Elimination => db::matches::WinReason::Elimination,
+        WinReason::Tiebreak(Tiebreak::Ships) => db::matches::WinReason::Ships,
+        WinReason::Tiebreak(Tiebreak::Planets) => db::matches::WinReason::Planets,
+        WinReason::Tiebreak(Tiebreak::Production) => db::matches::WinReason::Production,
+    }
+}
+
 async fn run_match_task(
     connection_pool: ConnectionPool,
     match_config: MatchConfig,
     match_id: i32,
-) -> MatchOutcome {
-    let outcome = runner::run_match(match_config).await;
+) -> Result<MatchOutcome, MatchError> {
+    let match_result = runner::run_match(match_config).await;
 
     // update match state in database
     let mut conn = connection_pool
@@ -172,18 +375,103 @@ async fn run_match_task(
         .await
         .expect("could not get database connection");
 
+    let outcome = match match_result {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            // TODO: set up proper logging infrastructure
+            eprintln!("match {} failed: {}", match_id, err);
+            let result = MatchResult::Failed {
+                reason: err.to_string(),
+            };
+            db::matches::save_match_result(match_id, result, &mut conn)
+                .expect("could not save match result");
+            return Err(err);
+        }
+    };
+
     let result = MatchResult::Finished {
         winner: outcome.winner.map(|w| (w - 1) as i32), // player numbers in matchrunner start at 1
+        win_reason: outcome.win_reason.map(db_win_reason),
+        num_turns: outcome.num_turns as i32,
     };
 
     conn.transaction(|conn| {
         for (player_id, player_outcome) in outcome.player_outcomes.iter().enumerate() {
-            let had_errors = player_outcome.had_errors || player_outcome.crashed;
+            let had_errors = player_outcome.had_errors
+                || player_outcome.crashed
+                || player_outcome.forfeit.is_some();
             db::matches::set_player_had_errors(match_id, player_id as i32, had_errors, conn)?;
         }
         db::matches::save_match_result(match_id, result, conn)
     })
     .expect("could not save match result");
 
-    outcome
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_precedence() {
+        let mut config = MatchLimitsConfig {
+            default: MatchLimits {
+                max_turns: Some(100),
+                turn_timeout_ms: Some(1000),
+                time_bank_ms: Some(5000),
+                ..Default::default()
+            },
+            ranked: MatchLimits {
+                turn_timeout_ms: Some(500),
+                time_bank_ms: Some(2000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        config.maps.insert(
+            "hex".to_string(),
+            MatchLimits {
+                time_bank_ms: Some(0),
+                ..Default::default()
+            },
+        );
+
+        let limits = config.limits_for(MatchType::Ranked, "hex");
+        assert_eq!(limits.max_turns, Some(100));
+        assert_eq!(limits.turn_timeout_ms, Some(500));
+        assert_eq!(limits.time_bank_ms, Some(0));
+
+        let limits = config.limits_for(MatchType::Ranked, "other");
+        assert_eq!(limits.turn_timeout_ms, Some(500));
+        assert_eq!(limits.time_bank_ms, Some(2000));
+
+        let limits = config.limits_for(MatchType::Demo, "other");
+        assert_eq!(limits, config.default);
+    }
+
+    #[test]
+    fn test_time_control() {
+        let limits = MatchLimits {
+            turn_timeout_ms: Some(200),
+            time_bank_ms: Some(1000),
+            time_bank_increment_ms: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.time_control(),
+            TimeControl {
+                turn_timeout: Duration::from_millis(200),
+                first_turn_timeout: None,
+                time_bank: Some(TimeBank {
+                    initial: Duration::from_millis(1000),
+                    increment: Duration::from_millis(50),
+                }),
+            }
+        );
+        assert_eq!(
+            MatchLimits::default().time_control(),
+            TimeControl::default()
+        );
+    }
 }
@@ -2,6 +2,7 @@
 // tied to the database or API layers.
 pub mod bots;
 pub mod client_api;
+pub mod maps;
 pub mod matches;
 pub mod ranking;
 pub mod registry;
@@ -1,11 +1,13 @@
 use crate::db::bots::BotVersion;
-use crate::db::maps::Map;
 use crate::{db::bots::Bot, DbPool, GlobalConfig};
 
 use crate::db;
-use crate::modules::matches::{MatchPlayer, RunMatch};
+use crate::modules::matches::{
+    MatchMap, MatchPlayer, MatchType, RunMatch, RunMatchError, MAX_MAP_SEED,
+};
 use diesel::{PgConnection, QueryResult};
 use rand::seq::SliceRandom;
+use rand::Rng;
 use std::collections::HashMap;
 use std::mem;
 use std::sync::Arc;
@@ -28,33 +30,42 @@ pub async fn run_ranker(config: Arc<GlobalConfig>, db_pool: DbPool) {
         interval.tick().await;
         let bots =
             db::bots::all_active_bots_with_version(&mut db_conn).expect("could not load bots");
-        if bots.len() < 2 {
-            // not enough bots to play a match
-            continue;
-        }
-
-        let selected_bots: Vec<(Bot, BotVersion)> = bots
-            .choose_multiple(&mut rand::thread_rng(), 2)
-            .cloned()
-            .collect();
 
         let maps = db::maps::get_ranked_maps(&mut db_conn).expect("could not load map");
-        let map = match maps.choose(&mut rand::thread_rng()).cloned() {
+        let mut match_maps: Vec<MatchMap> = maps.into_iter().map(MatchMap::Stored).collect();
+        if config.ranker_generated_maps {
+            match_maps.push(MatchMap::Generated {
+                seed: rand::thread_rng().gen_range(0..=MAX_MAP_SEED),
+            });
+        }
+        // only consider maps we have enough bots for
+        match_maps.retain(|map| map.num_players() <= bots.len());
+        let map = match match_maps.choose(&mut rand::thread_rng()).cloned() {
             None => continue, // no maps available
             Some(map) => map,
         };
 
-        play_ranked_match(config.clone(), map, selected_bots, db_pool.clone()).await;
+        let selected_bots: Vec<(Bot, BotVersion)> = bots
+            .choose_multiple(&mut rand::thread_rng(), map.num_players())
+            .cloned()
+            .collect();
+
+        let result = play_ranked_match(config.clone(), map, selected_bots, db_pool.clone()).await;
+        if let Err(err) = result {
+            // TODO: set up proper logging infrastructure
+            eprintln!("could not play ranked match: {}", err);
+            continue;
+        }
         recalculate_ratings(&mut db_conn).expect("could not recalculate ratings");
     }
 }
 
 pub async fn play_ranked_match(
     config: Arc<GlobalConfig>,
-    map: Map,
+    map: MatchMap,
     selected_bots: Vec<(Bot, BotVersion)>,
     db_pool: DbPool,
-) {
+) -> Result<(), RunMatchError> {
     let mut players = Vec::new();
     for (bot, bot_version) in selected_bots {
         let player = MatchPlayer::BotVersion {
@@ -64,12 +75,12 @@ pub async fn play_ranked_match(
         players.push(player);
     }
 
-    let (_, handle) = RunMatch::new(config, true, map, players)
+    let (_, handle) = RunMatch::new(config, MatchType::Ranked, map, players)
         .run(db_pool.clone())
-        .await
-        .expect("failed to run match");
+        .await?;
     // wait for match to complete, so that only one ranking match can be running
     let _outcome = handle.await;
+    Ok(())
 }
 
 fn recalculate_ratings(db_conn: &mut PgConnection) -> QueryResult<()> {
@@ -88,8 +99,9 @@ fn recalculate_ratings(db_conn: &mut PgConnection) -> QueryResult<()> {
 
 #[derive(Default)]
 struct MatchStats {
+    /// sum of the weighted scores
     total_score: f64,
-    num_matches: usize,
+    total_weight: f64,
 }
 
 fn fetch_match_stats(db_conn: &mut PgConnection) -> QueryResult<HashMap<(i32, i32), MatchStats>> {
@@ -97,32 +109,52 @@ fn fetch_match_stats(db_conn: &mut PgConnection) -> QueryResult<HashMap<(i32, i3
 
     let mut match_stats = HashMap::<(i32, i32), MatchStats>::new();
     for m in matches {
-        if m.match_players.len() != 2 {
-            continue;
+        let bot_ids: Option<Vec<i32>> = m
+            .match_players
+            .iter()
+            .map(|p| p.bot.as_ref().map(|bot| bot.id))
+            .collect();
+        if let Some(bot_ids) = bot_ids {
+            add_match_stats(&mut match_stats, &bot_ids, m.base.winner);
         }
-        let (mut a_id, mut b_id) = match (&m.match_players[0].bot, &m.match_players[1].bot) {
-            (Some(ref a), Some(ref b)) => (a.id, b.id),
-            _ => continue,
-        };
-        // score of player a
-        let mut score = match m.base.winner {
-            None => 0.5,
-            Some(0) => 1.0,
-            Some(1) => 0.0,
-            _ => panic!("invalid winner"),
-        };
+    }
+    Ok(match_stats)
+}
 
-        // put players in canonical order: smallest id first
-        if b_id < a_id {
-            mem::swap(&mut a_id, &mut b_id);
-            score = 1.0 - score;
-        }
+/// Record the result of a match between the given bots, in player order.
+/// A free-for-all match is counted as a match between every pair of players,
+/// weighted so that every player takes part in one match in total.
+fn add_match_stats(
+    match_stats: &mut HashMap<(i32, i32), MatchStats>,
+    bot_ids: &[i32],
+    winner: Option<i32>,
+) {
+    if bot_ids.len() < 2 {
+        return;
+    }
+    let weight = 1.0 / (bot_ids.len() - 1) as f64;
+
+    for a in 0..bot_ids.len() {
+        for b in (a + 1)..bot_ids.len() {
+            let (mut a_id, mut b_id) = (bot_ids[a], bot_ids[b]);
+            // score of player a. When a third player won, this is a tie.
+            let mut score = match winner {
+                Some(winner) if winner as usize == a => 1.0,
+                Some(winner) if winner as usize == b => 0.0,
+                _ => 0.5,
+            };
+
+            // put players in canonical order: smallest id first
+            if b_id < a_id {
+                mem::swap(&mut a_id, &mut b_id);
+                score = 1.0 - score;
+            }
 
-        let entry = match_stats.entry((a_id, b_id)).or_default();
-        entry.num_matches += 1;
-        entry.total_score += score;
+            let entry = match_stats.entry((a_id, b_id)).or_default();
+            entry.total_weight += weight;
+            entry.total_score += weight * score;
+        }
     }
-    Ok(match_stats)
 }
 
 /// Tokenizes player ids to a set of consecutive numbers
@@ -173,8 +205,8 @@ fn estimate_ratings_from_stats(match_stats: HashMap<(i32, i32), MatchStats>) ->
         input_records.push(RatingInputRecord {
             p1_ix: player_tokenizer.tokenize(a_id),
             p2_ix: player_tokenizer.tokenize(b_id),
-            score: stats.total_score / stats.num_matches as f64,
-            weight: stats.num_matches as f64,
+            score: stats.total_score / stats.total_weight,
+            weight: stats.total_weight,
         })
     }
 
@@ -339,4 +371,21 @@ mod tests {
         let predicted = sigmoid(ratings[0] - ratings[1]);
         assert!(0.5 < predicted && predicted < 0.8);
     }
+
+    #[test]
+    fn test_free_for_all_match_stats() {
+        let mut match_stats = HashMap::new();
+        add_match_stats(&mut match_stats, &[30, 10, 20], Some(0));
+
+        assert_eq!(match_stats.len(), 3);
+        // bot 30 won from both other bots
+        let stats = &match_stats[&(10, 30)];
+        assert!(is_close(stats.total_weight, 0.5));
+        assert!(is_close(stats.total_score, 0.0));
+        let stats = &match_stats[&(20, 30)];
+        assert!(is_close(stats.total_score, 0.0));
+        // the losers tied with each other
+        let stats = &match_stats[&(10, 20)];
+        assert!(is_close(stats.total_score / stats.total_weight, 0.5));
+    }
 }
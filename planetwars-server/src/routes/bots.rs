@@ -14,7 +14,7 @@ use std::sync::Arc;
 use thiserror;
 
 use crate::db;
-use crate::db::bots::{self, BotVersion};
+use crate::db::bots::{self, BotProtocol, BotVersion};
 use crate::db::ratings::{self, RankedBot};
 use crate::db::users::User;
 use crate::modules::bots::save_code_string;
@@ -120,6 +120,8 @@ pub async fn save_bot(
             let new_bot = bots::NewBot {
                 owner_id: Some(user.id),
                 name: &params.bot_name,
+                // bots saved from code are played with the python runner
+                protocol: BotProtocol::V1,
             };
 
             bots::create_bot(&new_bot, &mut conn).expect("could not create bot")
@@ -133,6 +135,9 @@ pub async fn save_bot(
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BotParams {
     name: String,
+    /// The protocol the bot speaks, V1 when not set
+    #[serde(default)]
+    protocol: BotProtocol,
 }
 
 // TODO: can we unify this with save_bot?
@@ -151,6 +156,7 @@ pub async fn create_bot(
     let bot_params = bots::NewBot {
         owner_id: Some(user.id),
         name: &params.name,
+        protocol: params.protocol,
     };
     let bot = bots::create_bot(&bot_params, &mut conn).unwrap();
     Ok((StatusCode::CREATED, Json(bot)))
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use crate::db;
 use crate::db::matches::{FullMatchData, FullMatchPlayerData};
 use crate::modules::bots::save_code_string;
-use crate::modules::matches::{MatchPlayer, RunMatch};
+use crate::modules::matches::{MatchMap, MatchPlayer, MatchType, RunMatch, MAX_MAP_SEED};
 use crate::ConnectionPool;
 use crate::GlobalConfig;
 use axum::extract::Extension;
@@ -21,6 +21,9 @@ pub struct SubmitBotParams {
     pub code: String,
     pub opponent_name: Option<String>,
     pub map_name: Option<String>,
+    /// When set, play on a map generated from this seed instead.
+    /// Must not exceed `MAX_MAP_SEED`.
+    pub map_seed: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -49,8 +52,19 @@ pub async fn submit_bot(
         db::bots::find_bot_with_version_by_name(&opponent_name, &mut conn)
             .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    let map =
-        db::maps::find_map_by_name(&map_name, &mut conn).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let (match_map, map) = match params.map_seed {
+        Some(seed) if seed > MAX_MAP_SEED => return Err(StatusCode::BAD_REQUEST),
+        Some(seed) => (MatchMap::Generated { seed }, None),
+        None => {
+            let map = db::maps::find_map_by_name(&map_name, &mut conn)
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            // demo matches are always played against a single opponent
+            if map.num_players != 2 {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            (MatchMap::Stored(map.clone()), Some(map))
+        }
+    };
 
     let player_bot_version = save_code_string(&params.code, None, &mut conn, &config)
         // TODO: can we recover from this?
@@ -58,8 +72,8 @@ pub async fn submit_bot(
 
     let run_match = RunMatch::new(
         config,
-        false,
-        map.clone(),
+        MatchType::Demo,
+        match_map,
         vec![
             MatchPlayer::BotVersion {
                 bot: None,
@@ -91,7 +105,7 @@ pub async fn submit_bot(
                 bot: Some(opponent_bot),
             },
         ],
-        map: Some(map),
+        map,
     };
 
     let api_match = super::matches::match_data_to_api(full_match_data);
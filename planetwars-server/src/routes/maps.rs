@@ -1,5 +1,6 @@
-use std::{collections::HashSet, fs::File, path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
+use crate::modules::maps::{check_map, check_map_name, save_map};
 use crate::{db, DatabaseConnection, GlobalConfig};
 use axum::{Extension, Json};
 use diesel::OptionalExtension;
@@ -9,10 +10,14 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct ApiMap {
     pub name: String,
+    pub num_players: i32,
 }
 
-fn map_into_api_map(map: db::maps::Map) -> ApiMap {
-    ApiMap { name: map.name }
+pub fn map_into_api_map(map: db::maps::Map) -> ApiMap {
+    ApiMap {
+        name: map.name,
+        num_players: map.num_players,
+    }
 }
 
 pub async fn list_maps(mut conn: DatabaseConnection) -> Result<Json<Vec<ApiMap>>, StatusCode> {
@@ -22,6 +27,7 @@ pub async fn list_maps(mut conn: DatabaseConnection) -> Result<Json<Vec<ApiMap>>
     Ok(Json(api_maps))
 }
 
+use planetwars_rules::classic::parse_classic_map;
 use planetwars_rules::config::Map as PlanetwarsMap;
 use serde_json::json;
 
@@ -29,7 +35,17 @@ use serde_json::json;
 pub struct CreateMapRequest {
     name: String,
     #[serde(flatten)]
-    map: PlanetwarsMap,
+    map: MapUpload,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MapUpload {
+    /// A map in the text format of the original AI Challenge
+    Classic {
+        classic_map: String,
+    },
+    Json(PlanetwarsMap),
 }
 
 pub async fn create_map(
@@ -69,61 +85,37 @@ pub async fn create_map(
         ));
     }
 
-    if let Err(error) = check_map(&params.map) {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            json!({
-                "error": error,
-            })
-            .to_string(),
-        ));
-    }
-
-    let rel_map_path = format!("{}.json", &params.name);
-
-    {
-        let full_map_path = PathBuf::from(&config.maps_directory).join(&rel_map_path);
-        let file = File::create(full_map_path).expect("failed to open file");
-        serde_json::to_writer_pretty(file, &params.map).expect("failed to write map");
-    }
-    let map = db::maps::create_map(
-        db::maps::NewMap {
-            name: &params.name,
-            file_path: &rel_map_path,
+    let map = match &params.map {
+        MapUpload::Json(map) => map.clone(),
+        MapUpload::Classic { classic_map } => match parse_classic_map(classic_map) {
+            Ok(map) => map,
+            Err(error) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    json!({
+                        "error": error.to_string(),
+                    })
+                    .to_string(),
+                ))
+            }
         },
-        &mut conn,
-    )
-    .expect("failed to save map");
-
-    Ok(Json(map_into_api_map(map)))
-}
-
-fn check_map(map: &PlanetwarsMap) -> Result<(), &str> {
-    let unique_names: HashSet<String> = map.planets.iter().map(|p| p.name.clone()).collect();
-    if unique_names.len() != map.planets.len() {
-        return Err("planet names not unique");
-    }
-    let players: HashSet<usize> = map.planets.iter().filter_map(|p| p.owner).collect();
-
-    if players != HashSet::from([1, 2]) {
-        return Err("maps should have player 1 and 2");
-    }
-
-    Ok(())
-}
+    };
 
-// TODO: remove duplication (bot name, user name)
-fn check_map_name(name: &str) -> Result<(), &str> {
-    if !name
-        .chars()
-        .all(|c| !c.is_uppercase() && (c.is_ascii_alphanumeric() || c == '_' || c == '-'))
-    {
-        return Err("Only [a-z-_] are allowed in map names");
-    }
+    let num_players = match check_map(&map) {
+        Ok(num_players) => num_players,
+        Err(error) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "error": error,
+                })
+                .to_string(),
+            ))
+        }
+    };
 
-    if name.len() < 3 || name.len() > 32 {
-        return Err("map name should be between 3 and 32 characters");
-    }
+    let map =
+        save_map(&params.name, &map, num_players, &mut conn, &config).expect("failed to save map");
 
-    Ok(())
+    Ok(Json(map_into_api_map(map)))
 }
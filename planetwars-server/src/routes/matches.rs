@@ -10,12 +10,12 @@ use std::{path::PathBuf, sync::Arc};
 use crate::{
     db::{
         self,
-        matches::{self, BotMatchOutcome, MatchState},
+        matches::{self, BotMatchOutcome, MatchState, WinReason},
     },
     DatabaseConnection, GlobalConfig,
 };
 
-use super::maps::ApiMap;
+use super::maps::{map_into_api_map, ApiMap};
 
 #[derive(Serialize, Deserialize)]
 pub struct ApiMatch {
@@ -24,7 +24,15 @@ pub struct ApiMatch {
     state: MatchState,
     players: Vec<ApiMatchPlayer>,
     winner: Option<i32>,
+    /// Why the winner won, eg. on ships at the turn limit
+    win_reason: Option<WinReason>,
+    /// Amount of turns played, for finished matches
+    num_turns: Option<i32>,
     map: Option<ApiMap>,
+    /// Seed the map was generated from, for matches on a generated map
+    map_seed: Option<i64>,
+    /// Why the match could not be played, for failed matches
+    failure_reason: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -141,7 +149,11 @@ pub fn match_data_to_api(data: matches::FullMatchData) -> ApiMatch {
             })
             .collect(),
         winner: data.base.winner,
-        map: data.map.map(|m| ApiMap { name: m.name }),
+        win_reason: data.base.win_reason,
+        num_turns: data.base.num_turns,
+        map: data.map.map(map_into_api_map),
+        map_seed: data.base.map_seed,
+        failure_reason: data.base.failure_reason,
     }
 }
 
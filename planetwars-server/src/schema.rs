@@ -4,9 +4,17 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "bot_protocol"))]
+    pub struct BotProtocol;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "match_state"))]
     pub struct MatchState;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "win_reason"))]
+    pub struct WinReason;
 }
 
 diesel::table! {
@@ -25,12 +33,14 @@ diesel::table! {
 diesel::table! {
     use diesel::sql_types::*;
     use crate::db_types::*;
+    use super::sql_types::BotProtocol;
 
     bots (id) {
         id -> Int4,
         owner_id -> Nullable<Int4>,
         name -> Text,
         active_version -> Nullable<Int4>,
+        protocol -> BotProtocol,
     }
 }
 
@@ -43,6 +53,7 @@ diesel::table! {
         name -> Text,
         file_path -> Text,
         is_ranked -> Bool,
+        num_players -> Int4,
     }
 }
 
@@ -62,6 +73,7 @@ diesel::table! {
     use diesel::sql_types::*;
     use crate::db_types::*;
     use super::sql_types::MatchState;
+    use super::sql_types::WinReason;
 
     matches (id) {
         id -> Int4,
@@ -71,6 +83,10 @@ diesel::table! {
         winner -> Nullable<Int4>,
         is_public -> Bool,
         map_id -> Nullable<Int4>,
+        map_seed -> Nullable<Int8>,
+        win_reason -> Nullable<WinReason>,
+        num_turns -> Nullable<Int4>,
+        failure_reason -> Nullable<Text>,
     }
 }
 
@@ -50,6 +50,7 @@ fn setup_simple_fixture(db_conn: &mut PgConnection, config: &GlobalConfig) {
         &db::bots::NewBot {
             owner_id: None,
             name: "simplebot",
+            protocol: db::bots::BotProtocol::V1,
         },
         db_conn,
     )
@@ -70,6 +71,7 @@ fn setup_simple_fixture(db_conn: &mut PgConnection, config: &GlobalConfig) {
         db::maps::NewMap {
             name: "hex",
             file_path: "hex.json",
+            num_players: 2,
         },
         db_conn,
     )
@@ -105,6 +107,9 @@ impl<'a> TestApp<'a> {
             registry_directory: create_subdir(data_dir.path(), "registry")?,
             registry_admin_password: "secret_admin_password".to_string(),
             ranker_enabled: false,
+            ranker_generated_maps: false,
+            tiebreaks: Vec::new(),
+            match_limits: Default::default(),
         });
         let db_guard = DB_LOCK.lock();
         let db_pool = create_db_pool(&config).await;
@@ -140,8 +145,14 @@ impl<'a> TestApp<'a> {
             bots.push((bot, bot_version));
         }
 
-        modules::ranking::play_ranked_match(self.config.clone(), map, bots, self.db_pool.clone())
-            .await;
+        modules::ranking::play_ranked_match(
+            self.config.clone(),
+            modules::matches::MatchMap::Stored(map),
+            bots,
+            self.db_pool.clone(),
+        )
+        .await
+        .expect("failed to run match");
     }
 }
 
@@ -342,6 +353,7 @@ async fn test_list_matches_with_errors() -> io::Result<()> {
                 &db::bots::NewBot {
                     owner_id: None,
                     name: "testbot",
+                    protocol: db::bots::BotProtocol::V1,
                 },
                 db_conn,
             )
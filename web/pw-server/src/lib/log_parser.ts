@@ -5,7 +5,12 @@ export type PlayerLogTurn = {
   stderr: string[];
 };
 
-type PlayerAction = Timeout | BadCommand | Dispatches;
+type PlayerAction = Forfeit | Timeout | BadCommand | Dispatches;
+
+type Forfeit = {
+  type: "forfeit";
+  reason: { kind: string; error?: string };
+};
 
 type Timeout = {
   type: "timeout";
@@ -45,7 +50,7 @@ export function parsePlayerLog(playerId: number, logText: string): PlayerLog {
   logLines.forEach((logLine) => {
     const logMessage = JSON.parse(logLine);
 
-    if (logMessage["type"] === "gamestate") {
+    if (isGameState(logMessage)) {
       if (turn) {
         playerLog.push(turn);
         turn = createEmptyLogTurn();
@@ -61,6 +66,7 @@ export function parsePlayerLog(playerId: number, logText: string): PlayerLog {
           turn.stderr.push(msg);
           break;
         }
+        case "forfeit":
         case "timeout":
         case "bad_command":
         case "dispatches": {
@@ -73,3 +79,55 @@ export function parsePlayerLog(playerId: number, logText: string): PlayerLog {
 
   return playerLog;
 }
+
+function isGameState(logMessage: object): boolean {
+  return logMessage["type"] === "gamestate" || logMessage["type"] === "gamestate_delta";
+}
+
+/**
+ * Extract all game states from a match log.
+ * Most states are logged as changes to the previous state, these are expanded here.
+ */
+export function parseGameStates(logText: string): object[] {
+  const logLines = logText.split("\n").slice(0, -1);
+
+  const states = [];
+  let previous = null;
+
+  logLines.forEach((logLine) => {
+    const logMessage = JSON.parse(logLine);
+
+    if (logMessage["type"] === "gamestate") {
+      previous = logMessage;
+      states.push(previous);
+    } else if (logMessage["type"] === "gamestate_delta") {
+      previous = applyStateDelta(previous, logMessage);
+      states.push(previous);
+    }
+  });
+
+  return states;
+}
+
+function applyStateDelta(previous: object, delta: object): object {
+  const planets = previous["planets"].map((planet) => ({ ...planet }));
+  delta["planets"].forEach(([index, owner, shipCount]) => {
+    planets[index]["owner"] = owner;
+    planets[index]["ship_count"] = shipCount;
+  });
+
+  const expeditions = delta["expeditions"].map((expedition) => {
+    if (typeof expedition === "number") {
+      // an expedition of the previous state, that traveled one more turn
+      const prev = previous["expeditions"].find((e) => e["id"] === expedition);
+      return { ...prev, turns_remaining: prev["turns_remaining"] - 1 };
+    }
+    return {
+      ...expedition,
+      origin: previous["planets"][expedition["origin"]]["name"],
+      destination: previous["planets"][expedition["destination"]]["name"],
+    };
+  });
+
+  return { ...previous, planets, expeditions };
+}